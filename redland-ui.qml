@@ -227,6 +227,10 @@ ShellRoot {
             sendCommand({"type": "get_status"})
         }
 
+        function subscribe() {
+            sendCommand({"type": "subscribe"})
+        }
+
         function setMode(newMode) {
             sendCommand({"type": "set_mode", "mode": newMode})
         }
@@ -236,19 +240,10 @@ ShellRoot {
         }
 
         Component.onCompleted: {
-            // Wait for daemon to start, then get initial status
+            // Wait for daemon to start, then follow its status updates
             Qt.callLater(() => {
-                statusTimer.start()
+                backend.subscribe()
             })
         }
-
-        Timer {
-            id: statusTimer
-            interval: 1000
-            repeat: true
-            onTriggered: {
-                backend.getStatus()
-            }
-        }
     }
 }
@@ -0,0 +1,205 @@
+use serde_json::json;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+use crate::cli::{Opts, StatusArgs};
+use crate::ctl::{self, CtlError};
+use crate::ipc::{IpcCommand, IpcResponse};
+
+/// How long to wait before reconnecting to a daemon that is not running.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum BarFormat {
+    Text,
+    Waybar,
+    I3bar,
+}
+
+/// What a bar needs from one status update.
+struct BarBlock {
+    text: String,
+    tooltip: String,
+    classes: Vec<&'static str>,
+    percentage: Option<i32>,
+    /// Override expiry or next transition, for bars without tooltips.
+    detail: Option<String>,
+}
+
+fn phase_icon(phase: &str) -> &'static str {
+    match phase {
+        "day" | "sunrise" => "☀",
+        "sunset" => "🌅",
+        _ => "🌙",
+    }
+}
+
+fn block_for(response: &IpcResponse) -> Option<BarBlock> {
+    let IpcResponse::Status {
+        requested_mode,
+        current_mode,
+        automatic_mode,
+        current_temp,
+        low_temp,
+        high_temp,
+        current_brightness,
+        polar,
+        next_transition,
+        override_expires,
+        ..
+    } = response
+    else {
+        return None;
+    };
+    let auto = requested_mode == "auto";
+    let icon = phase_icon(current_mode);
+    let text = if auto {
+        format!("{icon}ᴬ {current_temp}K")
+    } else {
+        format!("{icon} {current_temp}K")
+    };
+
+    let mut tooltip = vec![
+        format!("Phase: {current_mode} (automatic: {automatic_mode})"),
+        format!("Temperature: {current_temp}K ({low_temp}K-{high_temp}K)"),
+    ];
+    if *current_brightness < 1.0 {
+        tooltip.push(format!("Brightness: {:.0}%", current_brightness * 100.0));
+    }
+    if let Some(polar) = polar {
+        tooltip.push(format!("Sun: {}", polar.replace('_', " ")));
+    }
+    if let Some(next) = next_transition {
+        tooltip.push(format!("Next transition: {next}"));
+    }
+    if let Some(expires) = override_expires {
+        tooltip.push(format!("{requested_mode} override until {expires}"));
+    }
+    let detail = match (override_expires, next_transition) {
+        (Some(expires), _) => Some(format!("{requested_mode} until {expires}")),
+        (None, Some(next)) => Some(format!("next {next}")),
+        (None, None) => None,
+    };
+
+    let percentage = (high_temp > low_temp)
+        .then(|| ((current_temp - low_temp) * 100 / (high_temp - low_temp)).clamp(0, 100));
+    let phase_class = match current_mode.as_str() {
+        "day" => "day",
+        "sunrise" => "sunrise",
+        "sunset" => "sunset",
+        _ => "night",
+    };
+    Some(BarBlock {
+        text,
+        tooltip: tooltip.join("\n"),
+        classes: vec![phase_class, if auto { "auto" } else { "override" }],
+        percentage,
+        detail,
+    })
+}
+
+fn offline_block() -> BarBlock {
+    BarBlock {
+        text: String::new(),
+        tooltip: "redland is not running".to_string(),
+        classes: vec!["off"],
+        percentage: None,
+        detail: None,
+    }
+}
+
+/// i3bar has no CSS classes, so the phase is shown through the text colour.
+fn i3bar_color(class: &str) -> Option<&'static str> {
+    match class {
+        "day" => Some("#FFD75F"),
+        "sunrise" | "sunset" => Some("#FFAF5F"),
+        "night" => Some("#87AFFF"),
+        _ => None,
+    }
+}
+
+fn emit(out: &mut impl Write, format: BarFormat, block: &BarBlock) -> std::io::Result<()> {
+    match format {
+        BarFormat::Text => writeln!(out, "{}", block.text)?,
+        BarFormat::Waybar => {
+            let mut value = json!({
+                "text": block.text,
+                "tooltip": block.tooltip,
+                "class": block.classes,
+                "alt": block.classes[0],
+            });
+            if let Some(percentage) = block.percentage {
+                value["percentage"] = json!(percentage);
+            }
+            writeln!(out, "{}", value)?;
+        }
+        BarFormat::I3bar => {
+            let full_text = match &block.detail {
+                Some(detail) => format!("{} ({detail})", block.text),
+                None => block.text.clone(),
+            };
+            let mut value = json!({
+                "name": "redland",
+                "instance": "redland",
+                "full_text": full_text,
+                "short_text": block.text,
+            });
+            if let Some(color) = i3bar_color(block.classes[0]) {
+                value["color"] = json!(color);
+            }
+            writeln!(out, "{},", json!([value]))?;
+        }
+    }
+    out.flush()
+}
+
+/// Runs `redland status ...`: follows the daemon's event stream and prints
+/// one line per status change until the output is closed. Waits for the
+/// daemon to come back if it is not running.
+pub fn run(opts: &Opts, args: &StatusArgs) -> i32 {
+    let format = if args.waybar {
+        BarFormat::Waybar
+    } else if args.i3bar {
+        BarFormat::I3bar
+    } else {
+        BarFormat::Text
+    };
+    let Some(socket) = ctl::socket_path(opts) else {
+        eprintln!("{}", CtlError::NoSocket);
+        return CtlError::NoSocket.exit_code();
+    };
+
+    let mut out = std::io::stdout().lock();
+    if format == BarFormat::I3bar && writeln!(out, "{}\n[", json!({ "version": 1 })).is_err() {
+        return 0;
+    }
+
+    loop {
+        match ctl::connect(&socket, &IpcCommand::Subscribe) {
+            Ok(mut conn) => loop {
+                match conn.recv() {
+                    Ok(Some(response)) => {
+                        if let Some(block) = block_for(&response)
+                            && emit(&mut out, format, &block).is_err()
+                        {
+                            // The bar went away
+                            return 0;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        break;
+                    }
+                }
+            },
+            Err(CtlError::NotRunning(_)) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+        if emit(&mut out, format, &offline_block()).is_err() {
+            return 0;
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
@@ -0,0 +1,183 @@
+use anyhow::{Result, anyhow};
+use std::fmt;
+
+use crate::cli::LocateArgs;
+
+/// Name, country code, latitude, longitude and IANA zone, one city per line.
+const CITIES: &str = include_str!("../data/cities.tsv");
+
+/// Largest edit distance still accepted as a typo.
+const MAX_TYPOS: usize = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct City {
+    pub name: &'static str,
+    pub country: &'static str,
+    pub lat: f64,
+    pub lon: f64,
+    pub timezone: &'static str,
+}
+
+impl fmt::Display for City {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.name, self.country)
+    }
+}
+
+fn cities() -> impl Iterator<Item = City> {
+    CITIES
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            Some(City {
+                name: fields.next()?,
+                country: fields.next()?,
+                lat: fields.next()?.parse().ok()?,
+                lon: fields.next()?.parse().ok()?,
+                timezone: fields.next()?,
+            })
+        })
+}
+
+/// Lowercases, drops diacritics and treats punctuation as spaces, so
+/// "Zürich" matches "Zurich" and "st louis" matches "St. Louis".
+fn fold(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars().flat_map(char::to_lowercase) {
+        let c = match c {
+            'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ą' | 'ă' => 'a',
+            'ç' | 'ć' | 'č' => 'c',
+            'ď' | 'đ' => 'd',
+            'è' | 'é' | 'ê' | 'ë' | 'ę' | 'ě' => 'e',
+            'ì' | 'í' | 'î' | 'ï' => 'i',
+            'ł' | 'ľ' => 'l',
+            'ñ' | 'ń' | 'ň' => 'n',
+            'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ő' => 'o',
+            'ř' => 'r',
+            'ś' | 'š' | 'ş' | 'ș' => 's',
+            'ť' | 'ţ' | 'ț' => 't',
+            'ù' | 'ú' | 'û' | 'ü' | 'ů' | 'ű' => 'u',
+            'ý' | 'ÿ' => 'y',
+            'ź' | 'ż' | 'ž' => 'z',
+            'ß' => {
+                out.push_str("ss");
+                continue;
+            }
+            c if c.is_alphanumeric() => c,
+            _ => ' ',
+        };
+        out.push(c);
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// How well `name` matches `query`, lower is better: exact, prefix, word
+/// prefix, then typos by edit distance.
+fn score(name: &str, query: &str) -> Option<usize> {
+    if name == query {
+        return Some(0);
+    }
+    if name.starts_with(query) {
+        return Some(1);
+    }
+    if name.split(' ').any(|word| word.starts_with(query)) {
+        return Some(2);
+    }
+    let allowed = (query.chars().count() / 4).clamp(1, MAX_TYPOS);
+    let distance = edit_distance(name, query);
+    (distance <= allowed).then_some(2 + distance)
+}
+
+/// Splits `"Name, CC"` into the folded name and an upper-case country code.
+fn parse_query(query: &str) -> (String, Option<String>) {
+    if let Some((name, country)) = query.rsplit_once(',') {
+        let country = country.trim();
+        if country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic()) {
+            return (fold(name), Some(country.to_ascii_uppercase()));
+        }
+    }
+    (fold(query), None)
+}
+
+fn ranked(query: &str) -> Vec<(usize, City)> {
+    let (name, country) = parse_query(query);
+    if name.is_empty() {
+        return Vec::new();
+    }
+    let mut matches: Vec<_> = cities()
+        .filter(|city| country.as_deref().is_none_or(|cc| city.country == cc))
+        .filter_map(|city| score(&fold(city.name), &name).map(|s| (s, city)))
+        .collect();
+    matches.sort_by(|(a, x), (b, y)| {
+        a.cmp(b)
+            .then(x.name.cmp(y.name))
+            .then(x.country.cmp(y.country))
+    });
+    matches
+}
+
+/// Cities matching `query` ("Name" or "Name, CC"), best first.
+pub fn search(query: &str) -> Vec<City> {
+    ranked(query).into_iter().map(|(_, city)| city).collect()
+}
+
+/// The single best match for `query`. Fails when nothing matches or several
+/// cities match equally well, listing the candidates.
+pub fn lookup(query: &str) -> Result<City> {
+    let matches = ranked(query);
+    let Some(&(best, _)) = matches.first() else {
+        return Err(anyhow!(
+            "unknown city {query:?}; try `redland locate --search`"
+        ));
+    };
+    let mut best: Vec<City> = matches
+        .into_iter()
+        .take_while(|(s, _)| *s == best)
+        .map(|(_, city)| city)
+        .collect();
+    if best.len() > 1 {
+        let candidates: Vec<String> = best.iter().take(5).map(ToString::to_string).collect();
+        return Err(anyhow!(
+            "ambiguous city {query:?}: {}{}; add a country code such as \"{}\"",
+            candidates.join("; "),
+            if best.len() > 5 { "; ..." } else { "" },
+            best[0]
+        ));
+    }
+    Ok(best.remove(0))
+}
+
+/// Runs `redland locate --search ...`: lists matching cities.
+pub fn run(args: &LocateArgs) -> i32 {
+    let matches = search(&args.search);
+    if matches.is_empty() {
+        eprintln!("No cities match {:?}", args.search);
+        return 1;
+    }
+    for city in matches.iter().take(args.limit) {
+        println!(
+            "{:<28} {:>9.4} {:>10.4}  {}",
+            city.to_string(),
+            city.lat,
+            city.lon,
+            city.timezone
+        );
+    }
+    0
+}
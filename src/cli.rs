@@ -1,6 +1,16 @@
-use clap::{ArgAction, Parser, ValueEnum};
+use chrono::NaiveDate;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use std::path::PathBuf;
 
-#[derive(Copy, Clone, Debug, ValueEnum, PartialEq, Eq)]
+use crate::color::{Gamma, parse_gamma};
+use crate::keyframes::{Keyframe, parse_keyframe_spec};
+use crate::location::LocationSource;
+use crate::outputs::{OutputProfile, parse_profile_spec};
+use crate::scheduling::{Curve, Easing, PolarPolicy};
+
+#[derive(Copy, Clone, Debug, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ModeArg {
     Auto,
     Day,
@@ -8,6 +18,18 @@ pub enum ModeArg {
     Sunset,
 }
 
+impl ModeArg {
+    /// Lowercase name, as accepted by `--mode` and `set_mode`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ModeArg::Auto => "auto",
+            ModeArg::Day => "day",
+            ModeArg::Night => "night",
+            ModeArg::Sunset => "sunset",
+        }
+    }
+}
+
 #[derive(Parser, Debug, Clone)]
 #[command(
     name = "wlsunset-rs",
@@ -15,26 +37,91 @@ pub enum ModeArg {
     about = "Wayland screen temperature with sunrise/sunset + GeoClue"
 )]
 pub struct Opts {
-    /// Name/description of outputs to target (can repeat). If omitted, all.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file [default: $XDG_CONFIG_HOME/redland/config.toml]
+    #[arg(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// Name/description of outputs to target, exact or glob (can repeat). If omitted, all.
     #[arg(short = 'o', long = "output", action = ArgAction::Append)]
     pub outputs: Vec<String>,
 
-    /// Low color temperature at night (K)
-    #[arg(short = 't', long = "low", default_value_t = 4000)]
-    pub low_temp: i32,
+    /// Per-output profile PATTERN:key=value,... (keys: low, high, gamma,
+    /// brightness, enabled). Can repeat; the first matching pattern wins.
+    #[arg(short = 'p', long = "profile", value_parser = parse_profile_spec, action = ArgAction::Append)]
+    pub profiles: Vec<(String, OutputProfile)>,
 
-    /// High color temperature at day (K)
-    #[arg(short = 'T', long = "high", default_value_t = 6500)]
-    pub high_temp: i32,
+    /// Low color temperature at night (K) [default: 4000]
+    #[arg(short = 't', long = "low")]
+    pub low_temp: Option<i32>,
 
-    /// Latitude (degrees). If omitted, will try GeoClue if --geoclue is set.
+    /// High color temperature at day (K) [default: 6500]
+    #[arg(short = 'T', long = "high")]
+    pub high_temp: Option<i32>,
+
+    /// Gamma correction, one value or R:G:B per channel [default: 1.0]
+    #[arg(short = 'g', long = "gamma", value_parser = parse_gamma)]
+    pub gamma: Option<Gamma>,
+
+    /// Brightness level (0-1] scaling the scheduled brightness [default: 1]
+    #[arg(long = "brightness", value_name = "LEVEL")]
+    pub brightness: Option<f64>,
+
+    /// Lowest brightness ever applied, so the screen never goes black
+    /// [default: 0.1]
+    #[arg(long = "min-brightness", value_name = "LEVEL")]
+    pub min_brightness: Option<f64>,
+
+    /// Latitude (degrees). If omitted, the location comes from --location-source.
     #[arg(short = 'l', long = "lat")]
     pub latitude: Option<f64>,
 
-    /// Longitude (degrees). If omitted, will try GeoClue if --geoclue is set.
+    /// Longitude (degrees). If omitted, the location comes from --location-source.
     #[arg(short = 'L', long = "lon")]
     pub longitude: Option<f64>,
 
+    /// City name from the built-in list, optionally "Name, CC"
+    #[arg(long = "location", value_name = "NAME", conflicts_with_all = ["latitude", "longitude"])]
+    pub city: Option<String>,
+
+    /// Where to get the location from, in order of preference
+    /// [default: explicit,cache,geoclue,timezone,fixed]
+    #[arg(long = "location-source", value_enum, value_delimiter = ',')]
+    pub location_sources: Vec<LocationSource>,
+
+    /// Minimum distance in km the GeoClue location must move before the
+    /// schedule is recomputed [default: 10]
+    #[arg(long = "location-threshold", value_name = "KM")]
+    pub location_threshold: Option<f64>,
+
+    /// What to do when the sun neither rises nor sets [default: elevation]
+    #[arg(long = "polar", value_enum, value_name = "POLICY")]
+    pub polar: Option<PolarPolicy>,
+
+    /// How the temperature moves between night and day [default: time]
+    #[arg(long = "curve", value_enum)]
+    pub curve: Option<Curve>,
+
+    /// Sun elevation in degrees from which it is full day with the
+    /// elevation curve [default: 3]
+    #[arg(
+        long = "day-elevation",
+        value_name = "DEGREES",
+        allow_negative_numbers = true
+    )]
+    pub day_elevation: Option<f64>,
+
+    /// Sun elevation in degrees up to which it is full night with the
+    /// elevation curve [default: -6]
+    #[arg(
+        long = "night-elevation",
+        value_name = "DEGREES",
+        allow_negative_numbers = true
+    )]
+    pub night_elevation: Option<f64>,
+
     /// Manual sunrise time HH:MM (local). Disables lat/lon usage.
     #[arg(short = 'S', long = "sunrise")]
     pub sunrise: Option<String>,
@@ -43,11 +130,122 @@ pub struct Opts {
     #[arg(short = 's', long = "sunset")]
     pub sunset: Option<String>,
 
-    /// Transition duration in seconds around sunrise/sunset
-    #[arg(short = 'd', long = "duration", default_value_t = 1800)]
-    pub duration: i64,
+    /// Custom schedule keyframe AT:key=value,... where AT is HH:MM or
+    /// dawn/sunrise/sunset/dusk with an optional offset such as +30m (keys:
+    /// temp, brightness, ease). Can repeat; replaces the default schedule.
+    #[arg(long = "keyframe", value_parser = parse_keyframe_spec, action = ArgAction::Append)]
+    pub keyframes: Vec<Keyframe>,
+
+    /// Shape of transitions whose keyframe sets no `ease` [default: linear]
+    #[arg(long = "easing", value_enum)]
+    pub easing: Option<Easing>,
+
+    /// Where the morning fade starts: duration, civil, nautical, astronomical
+    /// or a sun elevation in degrees [default: duration]
+    #[arg(long = "dawn", value_name = "ANCHOR", allow_negative_numbers = true)]
+    pub dawn: Option<String>,
+
+    /// Where the evening fade ends: duration, civil, nautical, astronomical
+    /// or a sun elevation in degrees [default: duration]
+    #[arg(long = "dusk", value_name = "ANCHOR", allow_negative_numbers = true)]
+    pub dusk: Option<String>,
+
+    /// Transition duration in seconds around sunrise/sunset [default: 1800]
+    #[arg(short = 'd', long = "duration")]
+    pub duration: Option<i64>,
+
+    /// Seconds to fade over when the mode, range or an override changes;
+    /// 0 switches at once [default: 3]
+    #[arg(long = "fade", value_name = "SECONDS")]
+    pub fade: Option<f64>,
+
+    /// IPC socket path [default: $XDG_RUNTIME_DIR/redland.sock]
+    #[arg(long = "socket")]
+    pub socket: Option<PathBuf>,
+
+    /// Remember the temperature range, brightness and gamma set over IPC
+    /// across restarts
+    #[arg(long = "persist")]
+    pub persist: bool,
+
+    /// Do not expose the org.redland.Daemon D-Bus service
+    #[arg(long = "no-dbus")]
+    pub no_dbus: bool,
+
+    /// Operating mode override (auto/day/night) [default: auto]
+    #[arg(long = "mode", value_enum)]
+    pub mode: Option<ModeArg>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Control a running daemon over its IPC socket
+    Ctl(CtlArgs),
+    /// Follow the daemon status, printing a line per change for status bars
+    Status(StatusArgs),
+    /// Look up cities in the built-in list
+    Locate(LocateArgs),
+    /// Print the schedule that applies on a date
+    Schedule(ScheduleArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ScheduleArgs {
+    /// Date to show, YYYY-MM-DD [default: today]
+    #[arg(long = "date")]
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct LocateArgs {
+    /// City name, optionally followed by a country code ("Perth, GB")
+    #[arg(long = "search", value_name = "QUERY")]
+    pub search: String,
+
+    /// Maximum number of results
+    #[arg(long = "limit", default_value_t = 10)]
+    pub limit: usize,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct StatusArgs {
+    /// Emit Waybar custom module JSON (use with "return-type": "json")
+    #[arg(long = "waybar", conflicts_with = "i3bar")]
+    pub waybar: bool,
+
+    /// Emit the i3bar/swaybar JSON protocol
+    #[arg(long = "i3bar")]
+    pub i3bar: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct CtlArgs {
+    /// Print the raw JSON response instead of a summary
+    #[arg(long = "json", global = true)]
+    pub json: bool,
+
+    #[command(subcommand)]
+    pub action: CtlAction,
+}
 
-    /// Operating mode override (auto/day/night)
-    #[arg(long = "mode", value_enum, default_value_t = ModeArg::Auto)]
-    pub mode: ModeArg,
+#[derive(Subcommand, Debug, Clone)]
+pub enum CtlAction {
+    /// Show the daemon status
+    Status,
+    /// Set the operating mode
+    Mode {
+        #[arg(value_enum)]
+        mode: ModeArg,
+    },
+    /// Set the night/day temperature range (K)
+    Temp { low: i32, high: i32 },
+    /// Set the brightness level (0-1]
+    Brightness { level: f64 },
+    /// Set the gamma correction, one value or R:G:B
+    Gamma {
+        #[arg(value_parser = parse_gamma)]
+        gamma: Gamma,
+    },
+    /// Switch between day and night, returning to auto when it matches
+    Toggle,
 }
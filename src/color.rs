@@ -1,20 +1,156 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
 pub use tempergb::Color as Rgb;
 
+/// Range of color temperatures accepted anywhere in the daemon (K).
+pub const MIN_TEMP: i32 = 1000;
+pub const MAX_TEMP: i32 = 25000;
+
+pub fn validate_temperature(k: i32) -> Result<()> {
+    if !(MIN_TEMP..=MAX_TEMP).contains(&k) {
+        return Err(anyhow!(
+            "temperature {k}K out of range {MIN_TEMP}..={MAX_TEMP}"
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_temperature_range(low: i32, high: i32) -> Result<()> {
+    validate_temperature(low)?;
+    validate_temperature(high)?;
+    if high <= low {
+        return Err(anyhow!("high ({high}K) must be greater than low ({low}K)"));
+    }
+    Ok(())
+}
+
+pub fn validate_brightness(b: f64) -> Result<()> {
+    if !(b > 0.0 && b <= 1.0) {
+        return Err(anyhow!("brightness {b} out of range (0, 1]"));
+    }
+    Ok(())
+}
+
+/// Brightness the ramp is scaled by: `brightness`, clamped up to at least
+/// `floor`, so the screen is never dimmed below it.
+pub fn floored_brightness(brightness: f64, floor: f64) -> f64 {
+    brightness.clamp(floor, 1.0)
+}
+
+/// Range of gamma exponents accepted for each channel.
+pub const MIN_GAMMA: f64 = 0.1;
+pub const MAX_GAMMA: f64 = 10.0;
+
+/// Gamma exponent per channel. Reads and writes a single number when all
+/// channels agree and `"R:G:B"` otherwise.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "GammaValue", into = "GammaValue")]
+pub struct Gamma {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64,
+}
+
+impl Gamma {
+    pub const IDENTITY: Gamma = Gamma::uniform(1.0);
+
+    pub const fn uniform(g: f64) -> Self {
+        Self { r: g, g, b: g }
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        self.r == self.g && self.g == self.b
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for g in [self.r, self.g, self.b] {
+            if !(MIN_GAMMA..=MAX_GAMMA).contains(&g) {
+                return Err(anyhow!("gamma {g} out of range {MIN_GAMMA}..={MAX_GAMMA}"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Gamma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_uniform() {
+            write!(f, "{:.2}", self.r)
+        } else {
+            write!(f, "{:.2}:{:.2}:{:.2}", self.r, self.g, self.b)
+        }
+    }
+}
+
+/// Parses a gamma exponent for all channels, e.g. `1.1`, or one per channel
+/// as `R:G:B`, e.g. `1.0:0.9:0.85`.
+pub fn parse_gamma(s: &str) -> Result<Gamma> {
+    let channels = s
+        .split(':')
+        .map(|c| {
+            c.trim()
+                .parse()
+                .with_context(|| format!("invalid gamma {c:?}"))
+        })
+        .collect::<Result<Vec<f64>>>()?;
+    let gamma = match channels[..] {
+        [g] => Gamma::uniform(g),
+        [r, g, b] => Gamma { r, g, b },
+        _ => return Err(anyhow!("expected GAMMA or R:G:B, got {s:?}")),
+    };
+    gamma.validate()?;
+    Ok(gamma)
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum GammaValue {
+    Uniform(f64),
+    Channels(String),
+}
+
+impl TryFrom<GammaValue> for Gamma {
+    type Error = anyhow::Error;
+
+    fn try_from(value: GammaValue) -> Result<Self> {
+        match value {
+            GammaValue::Uniform(g) => {
+                let gamma = Gamma::uniform(g);
+                gamma.validate()?;
+                Ok(gamma)
+            }
+            GammaValue::Channels(s) => parse_gamma(&s),
+        }
+    }
+}
+
+impl From<Gamma> for GammaValue {
+    fn from(gamma: Gamma) -> Self {
+        if gamma.is_uniform() {
+            GammaValue::Uniform(gamma.r)
+        } else {
+            GammaValue::Channels(format!("{}:{}:{}", gamma.r, gamma.g, gamma.b))
+        }
+    }
+}
+
 pub fn blackbody_whitepoint_kelvin(k: i32) -> Rgb {
     tempergb::rgb_from_temperature(k)
 }
 
-pub fn fill_gamma_table(buf: &mut [u16], ramp_size: usize, wp: Rgb, gamma: f64) {
+pub fn fill_gamma_table(buf: &mut [u16], ramp_size: usize, wp: Rgb, gamma: Gamma, brightness: f64) {
     for i in 0..ramp_size {
-        let val = i as f64 / (ramp_size as f64 - 1.0);
+        let val = i as f64 / (ramp_size as f64 - 1.0) * brightness;
 
-        let corrected_r = ((val * wp.r() as f64 / 255.0) as f32).powf(1.0 / gamma as f32);
-        let corrected_g = ((val * wp.g() as f64 / 255.0) as f32).powf(1.0 / gamma as f32);
-        let corrected_b = ((val * wp.b() as f64 / 255.0) as f32).powf(1.0 / gamma as f32);
+        let corrected_r = ((val * wp.r() as f64 / 255.0) as f32).powf(1.0 / gamma.r as f32);
+        let corrected_g = ((val * wp.g() as f64 / 255.0) as f32).powf(1.0 / gamma.g as f32);
+        let corrected_b = ((val * wp.b() as f64 / 255.0) as f32).powf(1.0 / gamma.b as f32);
 
-        let rr = (corrected_r.max(0.0).min(1.0) as f64 * u16::MAX as f64).round() as u16;
-        let gg = (corrected_g.max(0.0).min(1.0) as f64 * u16::MAX as f64).round() as u16;
-        let bb = (corrected_b.max(0.0).min(1.0) as f64 * u16::MAX as f64).round() as u16;
+        let rr = (corrected_r.clamp(0.0, 1.0) as f64 * u16::MAX as f64).round() as u16;
+        let gg = (corrected_g.clamp(0.0, 1.0) as f64 * u16::MAX as f64).round() as u16;
+        let bb = (corrected_b.clamp(0.0, 1.0) as f64 * u16::MAX as f64).round() as u16;
 
         buf[i] = rr;
         buf[i + ramp_size] = gg;
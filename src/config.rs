@@ -0,0 +1,473 @@
+use anyhow::{Context, Result, anyhow};
+use inotify::{Inotify, WatchMask};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::cities;
+use crate::cli::{ModeArg, Opts};
+use crate::color::{Gamma, validate_brightness, validate_temperature};
+use crate::keyframes::{
+    Keyframe, Rule, Schedule, parse_date_range, parse_key_time, parse_level, parse_weekday,
+};
+use crate::location::{DEFAULT_SOURCES, LocationSource};
+use crate::outputs::{OutputFilter, OutputProfile, OutputProfiles};
+use crate::paths;
+use crate::scheduling::{
+    Curve, DEFAULT_DAY_ELEVATION, DEFAULT_NIGHT_ELEVATION, Easing, PolarPolicy, Twilight,
+    parse_hhmm, parse_twilight,
+};
+
+pub const DEFAULT_LOW_TEMP: i32 = 4000;
+pub const DEFAULT_HIGH_TEMP: i32 = 6500;
+pub const DEFAULT_DURATION: i64 = 1800;
+pub const DEFAULT_LOCATION_THRESHOLD: f64 = 10.0;
+pub const DEFAULT_FADE: f64 = 3.0;
+pub const DEFAULT_MIN_BRIGHTNESS: f64 = 0.1;
+
+/// Contents of `config.toml`. Every key is optional; command-line flags take
+/// precedence over values read from the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    outputs: Option<Vec<String>>,
+    low: Option<i32>,
+    high: Option<i32>,
+    gamma: Option<Gamma>,
+    brightness: Option<f64>,
+    min_brightness: Option<f64>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    location: Option<String>,
+    location_sources: Option<Vec<LocationSource>>,
+    location_threshold: Option<f64>,
+    sunrise: Option<String>,
+    sunset: Option<String>,
+    dawn: Option<String>,
+    dusk: Option<String>,
+    easing: Option<Easing>,
+    polar: Option<PolarPolicy>,
+    curve: Option<Curve>,
+    day_elevation: Option<f64>,
+    night_elevation: Option<f64>,
+    duration: Option<i64>,
+    fade: Option<f64>,
+    mode: Option<ModeArg>,
+    socket: Option<PathBuf>,
+    persist: Option<bool>,
+    dbus: Option<bool>,
+    #[serde(default)]
+    profile: Vec<FileProfile>,
+    #[serde(default)]
+    keyframe: Vec<FileKeyframe>,
+    #[serde(default)]
+    rule: Vec<FileRule>,
+}
+
+/// A `[[profile]]` section.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileProfile {
+    output: String,
+    low: Option<i32>,
+    high: Option<i32>,
+    gamma: Option<Gamma>,
+    brightness: Option<f64>,
+    enabled: Option<bool>,
+}
+
+impl FileProfile {
+    fn into_profile(self) -> (String, OutputProfile) {
+        let defaults = OutputProfile::default();
+        let profile = OutputProfile {
+            low_temp: self.low,
+            high_temp: self.high,
+            gamma: self.gamma,
+            brightness: self.brightness.unwrap_or(defaults.brightness),
+            enabled: self.enabled.unwrap_or(defaults.enabled),
+        };
+        (self.output, profile)
+    }
+}
+
+/// A `[[keyframe]]` section.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileKeyframe {
+    at: String,
+    temp: FileLevel,
+    brightness: Option<f64>,
+    ease: Option<Easing>,
+}
+
+/// A keyframe temperature: kelvin, `"low"` or `"high"`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FileLevel {
+    Kelvin(i32),
+    Name(String),
+}
+
+impl FileKeyframe {
+    fn into_keyframe(self) -> Result<Keyframe> {
+        let temp = match self.temp {
+            FileLevel::Kelvin(k) => parse_level(&k.to_string())?,
+            FileLevel::Name(name) => parse_level(&name)?,
+        };
+        let brightness = self.brightness.unwrap_or(1.0);
+        validate_brightness(brightness)?;
+        Ok(Keyframe {
+            at: parse_key_time(&self.at)?,
+            temp,
+            brightness,
+            ease: self.ease,
+        })
+    }
+}
+
+/// A `[[rule]]` section: keyframes for some weekdays or dates.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileRule {
+    name: Option<String>,
+    #[serde(default)]
+    days: Vec<String>,
+    #[serde(default)]
+    dates: Vec<String>,
+    #[serde(default)]
+    keyframe: Vec<FileKeyframe>,
+}
+
+impl FileRule {
+    fn into_rule(self) -> Result<Rule> {
+        if self.days.is_empty() && self.dates.is_empty() {
+            return Err(anyhow!("set `days`, `dates` or both"));
+        }
+        if self.keyframe.is_empty() {
+            return Err(anyhow!("no `keyframe` entries"));
+        }
+        Ok(Rule {
+            name: self.name,
+            weekdays: self
+                .days
+                .iter()
+                .map(|d| parse_weekday(d))
+                .collect::<Result<_>>()?,
+            dates: self
+                .dates
+                .iter()
+                .map(|d| parse_date_range(d))
+                .collect::<Result<_>>()?,
+            keyframes: keyframes(self.keyframe)?,
+        })
+    }
+}
+
+fn keyframes(file: Vec<FileKeyframe>) -> Result<Vec<Keyframe>> {
+    file.into_iter()
+        .enumerate()
+        .map(|(i, k)| {
+            let at = k.at.clone();
+            k.into_keyframe()
+                .with_context(|| format!("invalid `keyframe[{i}]` ({at:?})"))
+        })
+        .collect()
+}
+
+/// Effective settings after merging the config file with command-line flags.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub filter: OutputFilter,
+    pub profiles: OutputProfiles,
+    pub low_temp: i32,
+    pub high_temp: i32,
+    pub gamma: Gamma,
+    /// Level the scheduled brightness is scaled by
+    pub brightness: f64,
+    /// Lowest brightness ever applied
+    pub min_brightness: f64,
+    pub location: Option<(f64, f64)>,
+    pub location_sources: Vec<LocationSource>,
+    pub location_threshold: f64,
+    pub manual: Option<(i64, i64)>,
+    pub dawn: Twilight,
+    pub dusk: Twilight,
+    pub schedule: Schedule,
+    pub easing: Easing,
+    pub polar: PolarPolicy,
+    pub curve: Curve,
+    pub day_elevation: f64,
+    pub night_elevation: f64,
+    pub duration: i64,
+    pub fade: Duration,
+    pub mode: ModeArg,
+    pub socket: Option<PathBuf>,
+    pub persist: bool,
+    pub dbus: bool,
+}
+
+/// Path of the config file in use: `--config` or the XDG default.
+pub fn config_path(opts: &Opts) -> Option<PathBuf> {
+    opts.config.clone().or_else(paths::config_file)
+}
+
+impl Settings {
+    pub fn load(opts: &Opts) -> Result<Self> {
+        let file = match config_path(opts) {
+            Some(path) if path.exists() => read_config(&path)?,
+            Some(path) if opts.config.is_some() => {
+                return Err(anyhow!("config file {} not found", path.display()));
+            }
+            _ => FileConfig::default(),
+        };
+        Self::merge(opts, file)
+    }
+
+    fn merge(opts: &Opts, file: FileConfig) -> Result<Self> {
+        let outputs = if opts.outputs.is_empty() {
+            file.outputs.unwrap_or_default()
+        } else {
+            opts.outputs.clone()
+        };
+        let filter = OutputFilter::new(&outputs).context("invalid `outputs`")?;
+
+        // Command-line profiles come first so they win over the file
+        let mut profiles = opts.profiles.clone();
+        for (i, p) in file.profile.into_iter().enumerate() {
+            let (selector, profile) = p.into_profile();
+            profile
+                .validate()
+                .with_context(|| format!("invalid `profile[{i}]` ({selector:?})"))?;
+            profiles.push((selector, profile));
+        }
+        let profiles = OutputProfiles::new(&profiles)?;
+
+        let low_temp = opts.low_temp.or(file.low).unwrap_or(DEFAULT_LOW_TEMP);
+        let high_temp = opts.high_temp.or(file.high).unwrap_or(DEFAULT_HIGH_TEMP);
+        validate_temperature(low_temp).context("invalid `low`")?;
+        validate_temperature(high_temp).context("invalid `high`")?;
+        if high_temp <= low_temp {
+            return Err(anyhow!(
+                "`high` ({high_temp}) must be greater than `low` ({low_temp})"
+            ));
+        }
+
+        let brightness = opts.brightness.or(file.brightness).unwrap_or(1.0);
+        validate_brightness(brightness).context("invalid `brightness`")?;
+        let min_brightness = opts
+            .min_brightness
+            .or(file.min_brightness)
+            .unwrap_or(DEFAULT_MIN_BRIGHTNESS);
+        validate_brightness(min_brightness).context("invalid `min_brightness`")?;
+
+        let location = if let Some(name) = &opts.city {
+            Some(city_coordinates(name)?)
+        } else if opts.latitude.is_some() || opts.longitude.is_some() {
+            coordinates(opts.latitude.or(file.lat), opts.longitude.or(file.lon))?
+        } else {
+            match file.location {
+                Some(_) if file.lat.is_some() || file.lon.is_some() => {
+                    return Err(anyhow!("set either `location` or `lat`/`lon`, not both"));
+                }
+                Some(name) => Some(city_coordinates(&name)?),
+                None => coordinates(file.lat, file.lon)?,
+            }
+        };
+
+        let location_sources = if opts.location_sources.is_empty() {
+            file.location_sources
+                .unwrap_or_else(|| DEFAULT_SOURCES.to_vec())
+        } else {
+            opts.location_sources.clone()
+        };
+        if location_sources.is_empty() {
+            return Err(anyhow!("`location_sources` must not be empty"));
+        }
+
+        let location_threshold = opts
+            .location_threshold
+            .or(file.location_threshold)
+            .unwrap_or(DEFAULT_LOCATION_THRESHOLD);
+        if !(location_threshold >= 0.0 && location_threshold.is_finite()) {
+            return Err(anyhow!(
+                "`location_threshold` ({location_threshold}) must not be negative"
+            ));
+        }
+
+        let sunrise = opts.sunrise.clone().or(file.sunrise);
+        let sunset = opts.sunset.clone().or(file.sunset);
+        let manual = match (sunrise, sunset) {
+            (Some(a), Some(b)) => Some((
+                parse_hhmm(&a).with_context(|| format!("invalid `sunrise` {a:?}"))?,
+                parse_hhmm(&b).with_context(|| format!("invalid `sunset` {b:?}"))?,
+            )),
+            (None, None) => None,
+            _ => return Err(anyhow!("provide both `sunrise` and `sunset` or neither")),
+        };
+
+        let twilight = |key: &str, value: Option<String>| match value {
+            Some(v) => parse_twilight(&v).with_context(|| format!("invalid `{key}` {v:?}")),
+            None => Ok(Twilight::Duration),
+        };
+        let dawn = twilight("dawn", opts.dawn.clone().or(file.dawn))?;
+        let dusk = twilight("dusk", opts.dusk.clone().or(file.dusk))?;
+
+        let keyframes = if opts.keyframes.is_empty() {
+            keyframes(file.keyframe)?
+        } else {
+            opts.keyframes.clone()
+        };
+        let rules = file
+            .rule
+            .into_iter()
+            .enumerate()
+            .map(|(i, r)| {
+                let name = r.name.as_ref().map(|n| format!(" ({n:?})"));
+                r.into_rule()
+                    .with_context(|| format!("invalid `rule[{i}]`{}", name.unwrap_or_default()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let schedule = Schedule { keyframes, rules };
+
+        let day_elevation = opts
+            .day_elevation
+            .or(file.day_elevation)
+            .unwrap_or(DEFAULT_DAY_ELEVATION);
+        let night_elevation = opts
+            .night_elevation
+            .or(file.night_elevation)
+            .unwrap_or(DEFAULT_NIGHT_ELEVATION);
+        for (key, value) in [
+            ("day_elevation", day_elevation),
+            ("night_elevation", night_elevation),
+        ] {
+            if !(-90.0..=90.0).contains(&value) {
+                return Err(anyhow!("`{key}` ({value}) must be within -90..=90"));
+            }
+        }
+        if day_elevation <= night_elevation {
+            return Err(anyhow!(
+                "`day_elevation` ({day_elevation}) must be greater than `night_elevation` ({night_elevation})"
+            ));
+        }
+
+        let duration = opts.duration.or(file.duration).unwrap_or(DEFAULT_DURATION);
+        if duration < 0 {
+            return Err(anyhow!("`duration` ({duration}) must not be negative"));
+        }
+
+        let fade = opts.fade.or(file.fade).unwrap_or(DEFAULT_FADE);
+        if !(0.0..=60.0).contains(&fade) {
+            return Err(anyhow!("`fade` ({fade}) must be within 0..=60 seconds"));
+        }
+
+        Ok(Self {
+            filter,
+            profiles,
+            low_temp,
+            high_temp,
+            gamma: opts.gamma.or(file.gamma).unwrap_or(Gamma::IDENTITY),
+            brightness,
+            min_brightness,
+            location,
+            location_sources,
+            location_threshold,
+            manual,
+            dawn,
+            dusk,
+            schedule,
+            easing: opts.easing.or(file.easing).unwrap_or(Easing::Linear),
+            polar: opts.polar.or(file.polar).unwrap_or(PolarPolicy::Elevation),
+            curve: opts.curve.or(file.curve).unwrap_or(Curve::Time),
+            day_elevation,
+            night_elevation,
+            duration,
+            fade: Duration::from_secs_f64(fade),
+            mode: opts.mode.or(file.mode).unwrap_or(ModeArg::Auto),
+            socket: opts
+                .socket
+                .clone()
+                .or(file.socket)
+                .or_else(paths::socket_file),
+            persist: opts.persist || file.persist.unwrap_or(false),
+            dbus: !opts.no_dbus && file.dbus.unwrap_or(true),
+        })
+    }
+}
+
+fn coordinates(lat: Option<f64>, lon: Option<f64>) -> Result<Option<(f64, f64)>> {
+    match (lat, lon) {
+        (Some(lat), Some(lon)) => {
+            if !(-90.0..=90.0).contains(&lat) {
+                return Err(anyhow!("`lat` ({lat}) must be within -90..=90"));
+            }
+            if !(-180.0..=180.0).contains(&lon) {
+                return Err(anyhow!("`lon` ({lon}) must be within -180..=180"));
+            }
+            Ok(Some((lat, lon)))
+        }
+        (None, None) => Ok(None),
+        _ => Err(anyhow!("provide both `lat` and `lon` or neither")),
+    }
+}
+
+fn city_coordinates(name: &str) -> Result<(f64, f64)> {
+    let city = cities::lookup(name).context("invalid `location`")?;
+    Ok((city.lat, city.lon))
+}
+
+fn read_config(path: &Path) -> Result<FileConfig> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("parse {}", path.display()))
+}
+
+/// Watches the directory containing `path` and sends a notification whenever
+/// the config file is written, replaced or removed. Watching the directory
+/// rather than the file keeps working across editors' atomic renames.
+pub fn watch_config(path: &Path, tx: tokio::sync::mpsc::UnboundedSender<()>) -> Result<()> {
+    let dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("config path has no parent directory"))?;
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("config path has no file name"))?
+        .to_os_string();
+    let mut inotify = Inotify::init().context("inotify init")?;
+    inotify
+        .watches()
+        .add(
+            dir,
+            WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::DELETE,
+        )
+        .with_context(|| format!("watch {}", dir.display()))?;
+
+    thread::spawn(move || {
+        let mut buffer = [0u8; 4096];
+        loop {
+            match inotify.read_events_blocking(&mut buffer) {
+                Ok(mut events) => {
+                    let changed = events.any(|e| e.name == Some(file_name.as_os_str()));
+                    if changed && tx.send(()).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Config watcher failed: {err}");
+                    break;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+impl Settings {
+    /// Settings from command-line `args` and the config file `text`.
+    pub fn parse(args: &[&str], text: &str) -> Result<Self> {
+        let args = std::iter::once("redland").chain(args.iter().copied());
+        let opts = <Opts as clap::Parser>::try_parse_from(args)?;
+        Self::merge(&opts, toml::from_str(text)?)
+    }
+}
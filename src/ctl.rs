@@ -0,0 +1,232 @@
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{CtlAction, CtlArgs, Opts};
+use crate::color::Gamma;
+use crate::config::Settings;
+use crate::ipc::{IpcCommand, IpcResponse};
+use crate::paths;
+
+/// The daemon answered with an error response.
+pub const EXIT_REJECTED: i32 = 1;
+/// The daemon could not be talked to, or its reply was not understood.
+pub const EXIT_FAILED: i32 = 2;
+/// No daemon is listening on the socket (same code as `systemctl status`).
+pub const EXIT_NOT_RUNNING: i32 = 3;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CtlError {
+    #[error("redland is not running (no daemon on {0})")]
+    NotRunning(PathBuf),
+    #[error("no IPC socket path; set XDG_RUNTIME_DIR or pass --socket")]
+    NoSocket,
+    #[error("IPC error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid response from daemon: {0}")]
+    Protocol(#[from] serde_json::Error),
+}
+
+impl CtlError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CtlError::NotRunning(_) | CtlError::NoSocket => EXIT_NOT_RUNNING,
+            CtlError::Io(_) | CtlError::Protocol(_) => EXIT_FAILED,
+        }
+    }
+}
+
+/// Socket of the running daemon: `--socket`, then the config file, then the
+/// XDG default. A broken config file must not stop `ctl` from working.
+pub fn socket_path(opts: &Opts) -> Option<PathBuf> {
+    match Settings::load(opts) {
+        Ok(settings) => settings.socket,
+        Err(_) => opts.socket.clone().or_else(paths::socket_file),
+    }
+}
+
+/// Opens a connection to the daemon and sends one command.
+pub fn connect(socket: &Path, command: &IpcCommand) -> Result<IpcConnection, CtlError> {
+    let stream = UnixStream::connect(socket).map_err(|e| match e.kind() {
+        ErrorKind::NotFound | ErrorKind::ConnectionRefused => {
+            CtlError::NotRunning(socket.to_path_buf())
+        }
+        _ => CtlError::Io(e),
+    })?;
+    let mut conn = IpcConnection {
+        reader: BufReader::new(stream.try_clone()?),
+        writer: stream,
+    };
+    conn.send(command)?;
+    Ok(conn)
+}
+
+pub struct IpcConnection {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl IpcConnection {
+    pub fn send(&mut self, command: &IpcCommand) -> Result<(), CtlError> {
+        let mut line = serde_json::to_string(command)?;
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Reads the next response line; `None` once the daemon hangs up.
+    pub fn recv(&mut self) -> Result<Option<IpcResponse>, CtlError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(line.trim())?))
+    }
+}
+
+pub fn request(socket: &Path, command: &IpcCommand) -> Result<IpcResponse, CtlError> {
+    let mut conn = connect(socket, command)?;
+    conn.recv()?.ok_or_else(|| {
+        CtlError::Io(std::io::Error::new(
+            ErrorKind::UnexpectedEof,
+            "daemon closed the connection",
+        ))
+    })
+}
+
+/// Runs `redland ctl ...` and returns the process exit code.
+pub fn run(opts: &Opts, args: &CtlArgs) -> i32 {
+    let command = match &args.action {
+        CtlAction::Status => IpcCommand::GetStatus,
+        CtlAction::Mode { mode } => IpcCommand::SetMode {
+            mode: mode.name().to_string(),
+        },
+        CtlAction::Temp { low, high } => IpcCommand::SetTemperature {
+            low: *low,
+            high: *high,
+        },
+        CtlAction::Brightness { level } => IpcCommand::SetBrightness { brightness: *level },
+        CtlAction::Gamma { gamma } => IpcCommand::SetGamma { gamma: *gamma },
+        CtlAction::Toggle => IpcCommand::Toggle,
+    };
+    let Some(socket) = socket_path(opts) else {
+        eprintln!("{}", CtlError::NoSocket);
+        return CtlError::NoSocket.exit_code();
+    };
+    let response = match request(&socket, &command) {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("{}", e);
+            return e.exit_code();
+        }
+    };
+
+    if args.json {
+        match serde_json::to_string(&response) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("{}", e);
+                return EXIT_FAILED;
+            }
+        }
+    } else {
+        print_response(&response);
+    }
+    match response {
+        IpcResponse::Error { .. } => EXIT_REJECTED,
+        _ => 0,
+    }
+}
+
+fn print_response(response: &IpcResponse) {
+    match response {
+        IpcResponse::Status {
+            requested_mode,
+            current_mode,
+            automatic_mode,
+            current_temp,
+            low_temp,
+            high_temp,
+            gamma,
+            current_brightness,
+            brightness,
+            location,
+            sun_times,
+            polar,
+            next_transition,
+            override_expires,
+            outputs,
+        } => {
+            println!(
+                "Mode:        {} (automatic: {})",
+                requested_mode, automatic_mode
+            );
+            println!("Phase:       {}", current_mode);
+            println!(
+                "Temperature: {}K (range {}K-{}K)",
+                current_temp, low_temp, high_temp
+            );
+            println!(
+                "Brightness:  {:.2} (level {:.2})",
+                current_brightness, brightness
+            );
+            println!("Gamma:       {}", gamma);
+            if let Some((lat, lon)) = location {
+                println!("Location:    {:.4}, {:.4}", lat, lon);
+            }
+            if let Some((sunrise, sunset)) = sun_times {
+                println!("Sun times:   {} - {}", sunrise, sunset);
+            }
+            if let Some(polar) = polar {
+                println!("Polar:       {}", polar.replace('_', " "));
+            }
+            if let Some(next) = next_transition {
+                println!("Next change: {}", next);
+            }
+            if let Some(expires) = override_expires {
+                println!("Override:    until {}", expires);
+            }
+            if !outputs.is_empty() {
+                println!("Outputs:");
+            }
+            for output in outputs {
+                let name = output
+                    .name
+                    .as_deref()
+                    .or(output.description.as_deref())
+                    .unwrap_or("(unnamed)");
+                match output.temperature {
+                    Some(temp) if output.enabled => println!(
+                        "  {:<12} {}K gamma {} brightness {:.2} ({} uploads)",
+                        name,
+                        temp,
+                        output.gamma.unwrap_or(Gamma::IDENTITY),
+                        output.brightness.unwrap_or(1.0),
+                        output.uploads
+                    ),
+                    _ => println!("  {:<12} not managed", name),
+                }
+            }
+        }
+        IpcResponse::Error { message } => eprintln!("Error: {}", message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_have_distinct_exit_codes() {
+        let denied = std::io::Error::from(ErrorKind::PermissionDenied);
+        let malformed = serde_json::from_str::<IpcResponse>("{").unwrap_err();
+        assert_eq!(CtlError::Io(denied).exit_code(), EXIT_FAILED);
+        assert_eq!(CtlError::Protocol(malformed).exit_code(), EXIT_FAILED);
+        assert_eq!(CtlError::NoSocket.exit_code(), EXIT_NOT_RUNNING);
+        assert_eq!(
+            CtlError::NotRunning(PathBuf::from("/nonexistent")).exit_code(),
+            EXIT_NOT_RUNNING
+        );
+        assert_ne!(EXIT_FAILED, EXIT_REJECTED);
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::{Context, Result};
+use zbus::fdo;
+
+use crate::color::Gamma;
+use crate::ipc::{IpcCommand, IpcContext, IpcResponse, SharedAppState, handle_command};
+
+pub const BUS_NAME: &str = "org.redland.Daemon";
+pub const OBJECT_PATH: &str = "/org/redland/Daemon";
+
+/// `org.redland.Daemon` on the session bus. Properties mirror the IPC
+/// status; methods go through the same command handling as the JSONL
+/// protocol.
+struct DaemonInterface {
+    ctx: IpcContext,
+}
+
+impl DaemonInterface {
+    fn state(&self) -> SharedAppState {
+        self.ctx.shared_state.lock().unwrap().clone()
+    }
+
+    fn run(&self, command: IpcCommand) -> fdo::Result<()> {
+        match handle_command(command, &self.ctx) {
+            IpcResponse::Error { message } => Err(fdo::Error::InvalidArgs(message)),
+            IpcResponse::Status { .. } => Ok(()),
+        }
+    }
+}
+
+#[zbus::interface(name = "org.redland.Daemon")]
+impl DaemonInterface {
+    /// Applied color temperature (K).
+    #[zbus(property)]
+    fn temperature(&self) -> i32 {
+        self.state().current_temp
+    }
+
+    #[zbus(property)]
+    fn low_temperature(&self) -> i32 {
+        self.state().low_temp
+    }
+
+    #[zbus(property)]
+    fn high_temperature(&self) -> i32 {
+        self.state().high_temp
+    }
+
+    /// Brightness applied before per-output profiles, 0-1.
+    #[zbus(property, name = "Brightness")]
+    fn current_brightness(&self) -> f64 {
+        self.state().current_brightness
+    }
+
+    /// Gamma correction as `[red, green, blue]`.
+    #[zbus(property)]
+    fn gamma(&self) -> Vec<f64> {
+        let gamma = self.state().gamma;
+        vec![gamma.r, gamma.g, gamma.b]
+    }
+
+    /// Applied phase: night, sunrise, day or sunset.
+    #[zbus(property)]
+    fn phase(&self) -> String {
+        self.state().current_mode.name().to_string()
+    }
+
+    /// Phase the schedule alone would apply.
+    #[zbus(property)]
+    fn automatic_phase(&self) -> String {
+        self.state().automatic_mode.name().to_string()
+    }
+
+    /// auto, day, night or sunset.
+    #[zbus(property)]
+    fn requested_mode(&self) -> String {
+        self.state().requested_mode.name().to_string()
+    }
+
+    /// `[latitude, longitude]`, empty when unknown.
+    #[zbus(property)]
+    fn location(&self) -> Vec<f64> {
+        self.state()
+            .location
+            .map(|(lat, lon)| vec![lat, lon])
+            .unwrap_or_default()
+    }
+
+    /// `[sunrise, sunset]` as local HH:MM, empty when unknown.
+    #[zbus(property)]
+    fn sun_times(&self) -> Vec<String> {
+        self.state()
+            .sun_times
+            .map(|(sunrise, sunset)| vec![sunrise, sunset])
+            .unwrap_or_default()
+    }
+
+    fn set_mode(&self, mode: String) -> fdo::Result<()> {
+        self.run(IpcCommand::SetMode { mode })
+    }
+
+    fn set_temperature(&self, low: i32, high: i32) -> fdo::Result<()> {
+        self.run(IpcCommand::SetTemperature { low, high })
+    }
+
+    fn set_brightness(&self, brightness: f64) -> fdo::Result<()> {
+        self.run(IpcCommand::SetBrightness { brightness })
+    }
+
+    fn set_gamma(&self, red: f64, green: f64, blue: f64) -> fdo::Result<()> {
+        self.run(IpcCommand::SetGamma {
+            gamma: Gamma {
+                r: red,
+                g: green,
+                b: blue,
+            },
+        })
+    }
+
+    /// Flips between day and night; returns the newly requested mode.
+    fn toggle(&self) -> fdo::Result<String> {
+        self.run(IpcCommand::Toggle)?;
+        Ok(self.requested_mode())
+    }
+}
+
+/// Claims `org.redland.Daemon` and emits `PropertiesChanged` whenever the
+/// published status changes. Runs until the status channel closes.
+pub async fn serve(ctx: IpcContext) -> Result<()> {
+    serve_on(zbus::connection::Builder::session()?, ctx).await
+}
+
+/// [`serve`] on the bus `builder` connects to.
+async fn serve_on(builder: zbus::connection::Builder<'_>, ctx: IpcContext) -> Result<()> {
+    let mut status_rx = ctx.status_rx.clone();
+    let shared_state = ctx.shared_state.clone();
+    let conn = builder
+        .name(BUS_NAME)?
+        .serve_at(OBJECT_PATH, DaemonInterface { ctx })?
+        .build()
+        .await
+        .with_context(|| format!("claim {BUS_NAME} on the session bus"))?;
+    let iface_ref = conn
+        .object_server()
+        .interface::<_, DaemonInterface>(OBJECT_PATH)
+        .await?;
+
+    let mut previous = shared_state.lock().unwrap().clone();
+    status_rx.mark_unchanged();
+    while status_rx.changed().await.is_ok() {
+        let current = shared_state.lock().unwrap().clone();
+        let iface = iface_ref.get().await;
+        let ctxt = iface_ref.signal_context();
+        if previous.current_temp != current.current_temp {
+            iface.temperature_changed(ctxt).await?;
+        }
+        if previous.low_temp != current.low_temp {
+            iface.low_temperature_changed(ctxt).await?;
+        }
+        if previous.high_temp != current.high_temp {
+            iface.high_temperature_changed(ctxt).await?;
+        }
+        if previous.current_brightness != current.current_brightness {
+            iface.brightness_changed(ctxt).await?;
+        }
+        if previous.gamma != current.gamma {
+            iface.gamma_changed(ctxt).await?;
+        }
+        if previous.current_mode != current.current_mode {
+            iface.phase_changed(ctxt).await?;
+        }
+        if previous.automatic_mode != current.automatic_mode {
+            iface.automatic_phase_changed(ctxt).await?;
+        }
+        if previous.requested_mode != current.requested_mode {
+            iface.requested_mode_changed(ctxt).await?;
+        }
+        if previous.location != current.location {
+            iface.location_changed(ctxt).await?;
+        }
+        if previous.sun_times != current.sun_times {
+            iface.sun_times_changed(ctxt).await?;
+        }
+        previous = current;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Command, Stdio};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::sync::{mpsc, watch};
+
+    use crate::cli::ModeArg;
+    use crate::ipc::{Control, format_status_response};
+
+    fn context() -> (
+        IpcContext,
+        mpsc::UnboundedReceiver<Control>,
+        watch::Sender<IpcResponse>,
+    ) {
+        let shared_state = Arc::new(Mutex::new(SharedAppState::new(4000, 6500)));
+        let (control_tx, control_rx) = mpsc::unbounded_channel();
+        let (status_tx, status_rx) =
+            watch::channel(format_status_response(&shared_state.lock().unwrap()));
+        let ctx = IpcContext {
+            shared_state,
+            control_tx,
+            status_rx,
+        };
+        (ctx, control_rx, status_tx)
+    }
+
+    fn assert_invalid_args(result: fdo::Result<()>) {
+        match result {
+            Err(fdo::Error::InvalidArgs(_)) => {}
+            other => panic!("expected InvalidArgs, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn methods_go_through_command_handling() {
+        let (ctx, mut control_rx, _status_tx) = context();
+        let iface = DaemonInterface { ctx };
+
+        iface.set_mode("night".to_string()).unwrap();
+        assert_eq!(
+            control_rx.try_recv().unwrap(),
+            Control::SetMode(ModeArg::Night)
+        );
+        assert_eq!(iface.requested_mode(), "night");
+
+        iface.set_temperature(3000, 6000).unwrap();
+        assert_eq!(
+            control_rx.try_recv().unwrap(),
+            Control::SetTemperature {
+                low: 3000,
+                high: 6000
+            }
+        );
+        assert_eq!(iface.low_temperature(), 3000);
+        assert_eq!(iface.high_temperature(), 6000);
+
+        iface.set_brightness(0.5).unwrap();
+        assert_eq!(control_rx.try_recv().unwrap(), Control::SetBrightness(0.5));
+
+        // Showing day, so toggling asks for night
+        assert_eq!(iface.toggle().unwrap(), "night");
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        let (ctx, mut control_rx, _status_tx) = context();
+        let iface = DaemonInterface { ctx };
+
+        assert_invalid_args(iface.set_mode("dusk".to_string()));
+        assert_invalid_args(iface.set_temperature(6500, 4000));
+        assert_invalid_args(iface.set_temperature(500, 6500));
+        assert_invalid_args(iface.set_brightness(0.0));
+        assert_invalid_args(iface.set_gamma(1.0, 0.01, 1.0));
+        assert!(control_rx.try_recv().is_err());
+        assert_eq!(iface.requested_mode(), "auto");
+        assert_eq!(iface.low_temperature(), 4000);
+    }
+
+    /// Runs against a private bus: `cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore = "needs dbus-daemon"]
+    async fn serves_on_a_private_bus() {
+        let mut bus = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("start dbus-daemon");
+        let mut address = String::new();
+        BufReader::new(bus.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        let address = address.trim();
+
+        let (ctx, mut control_rx, _status_tx) = context();
+        let builder = zbus::connection::Builder::address(address).unwrap();
+        let server = tokio::spawn(serve_on(builder, ctx));
+
+        let conn = zbus::connection::Builder::address(address)
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        let proxy = zbus::Proxy::new(&conn, BUS_NAME, OBJECT_PATH, BUS_NAME)
+            .await
+            .unwrap();
+        // The service may not have claimed its name yet
+        let mut attempts = 0;
+        while let Err(e) = proxy.call_method("SetMode", &("night",)).await {
+            attempts += 1;
+            assert!(attempts < 50, "service never appeared: {e}");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(
+            control_rx.recv().await,
+            Some(Control::SetMode(ModeArg::Night))
+        );
+
+        match proxy.call_method("SetMode", &("dusk",)).await {
+            Err(zbus::Error::MethodError(name, _, _)) => {
+                assert_eq!(name.as_str(), "org.freedesktop.DBus.Error.InvalidArgs");
+            }
+            other => panic!("expected InvalidArgs, got {other:?}"),
+        }
+        let temperature: i32 = proxy.get_property("HighTemperature").await.unwrap();
+        assert_eq!(temperature, 6500);
+
+        server.abort();
+        bus.kill().unwrap();
+        bus.wait().unwrap();
+    }
+}
@@ -1,49 +1,125 @@
 use anyhow::{Context, Result, anyhow};
-use std::thread;
-use std::time::{Duration, Instant};
-use zbus::blocking::Connection as ZbusConnection;
+use futures_util::StreamExt;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, Proxy};
 
-pub fn geoclue_lat_lon(desktop_id: &str) -> Result<(f64, f64)> {
-    let conn = ZbusConnection::system().context("connect to system bus")?;
-    let manager = zbus::blocking::Proxy::new(
+pub const DESKTOP_ID: &str = "wlsunset-rs.desktop";
+
+const SERVICE: &str = "org.freedesktop.GeoClue2";
+/// How long to wait before talking to GeoClue again after it failed or left
+/// the bus.
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Great-circle distance between two `(lat, lon)` points in kilometres.
+pub fn distance_km(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * h.sqrt().asin()
+}
+
+/// Follows GeoClue's location and sends coordinates whenever they move by at
+/// least `threshold_km` from the last ones sent. If GeoClue is missing or
+/// leaves the bus, retries periodically. Returns once `tx` is closed.
+pub async fn track(desktop_id: &str, threshold_km: f64, tx: UnboundedSender<(f64, f64)>) {
+    let mut last = None;
+    loop {
+        match follow(desktop_id, threshold_km, &tx, &mut last).await {
+            Ok(()) => eprintln!("GeoClue left the bus; keeping last location"),
+            Err(e) => eprintln!("GeoClue unavailable: {e:#}"),
+        }
+        if tx.is_closed() {
+            return;
+        }
+        tokio::time::sleep(RETRY_DELAY).await;
+    }
+}
+
+/// One GeoClue client session. Returns `Ok` when GeoClue goes away.
+async fn follow(
+    desktop_id: &str,
+    threshold_km: f64,
+    tx: &UnboundedSender<(f64, f64)>,
+    last: &mut Option<(f64, f64)>,
+) -> Result<()> {
+    let conn = Connection::system()
+        .await
+        .context("connect to system bus")?;
+    let manager = Proxy::new(
         &conn,
-        "org.freedesktop.GeoClue2",
+        SERVICE,
         "/org/freedesktop/GeoClue2/Manager",
         "org.freedesktop.GeoClue2.Manager",
-    )?;
+    )
+    .await?;
+    let mut owner_changes = manager.receive_owner_changed().await?;
 
-    let client_path: zbus::zvariant::OwnedObjectPath = manager.call("CreateClient", &())?;
-    let client = zbus::blocking::Proxy::new(
+    let client_path: OwnedObjectPath = manager.call("CreateClient", &()).await?;
+    let client = Proxy::new(
         &conn,
-        "org.freedesktop.GeoClue2",
+        SERVICE,
         client_path.as_str(),
         "org.freedesktop.GeoClue2.Client",
-    )?;
-
-    client.set_property("DesktopId", desktop_id)?;
-    client.set_property("RequestedAccuracyLevel", 3u32)?;
-    client.call::<_, (), ()>("Start", &())?;
-
-    let deadline = Instant::now() + Duration::from_secs(8);
-    let mut loc_path: zbus::zvariant::OwnedObjectPath = client.get_property("Location")?;
-    while {
-        let path = loc_path.as_str();
-        path.is_empty() || path == "/"
-    } {
-        if Instant::now() >= deadline {
-            return Err(anyhow!("GeoClue did not provide a location"));
+    )
+    .await?;
+    client.set_property("DesktopId", desktop_id).await?;
+    client.set_property("RequestedAccuracyLevel", 3u32).await?;
+    // GeoClue filters small moves itself; the check below covers the rest
+    client
+        .set_property("DistanceThreshold", (threshold_km * 1000.0) as u32)
+        .await?;
+    let mut updates = client.receive_signal("LocationUpdated").await?;
+    client.call::<_, _, ()>("Start", &()).await?;
+
+    // A location may already be known before the first signal
+    let current: OwnedObjectPath = client.get_property("Location").await?;
+    if current.as_str() != "/" {
+        send_location(&conn, &current, threshold_km, tx, last).await?;
+    }
+
+    loop {
+        tokio::select! {
+            Some(message) = updates.next() => {
+                let (_old, new): (OwnedObjectPath, OwnedObjectPath) = message
+                    .body()
+                    .deserialize()
+                    .context("parse LocationUpdated")?;
+                send_location(&conn, &new, threshold_km, tx, last).await?;
+            }
+            owner = owner_changes.next() => {
+                if !matches!(owner, Some(Some(_))) {
+                    return Ok(());
+                }
+            }
+            _ = tx.closed() => return Ok(()),
         }
-        thread::sleep(Duration::from_millis(200));
-        loc_path = client.get_property("Location")?;
     }
-    let location = zbus::blocking::Proxy::new(
-        &conn,
-        "org.freedesktop.GeoClue2",
-        loc_path.as_str(),
-        "org.freedesktop.GeoClue2.Location",
-    )?;
+}
 
-    let lat: f64 = location.get_property("Latitude")?;
-    let lon: f64 = location.get_property("Longitude")?;
-    Ok((lat, lon))
+async fn send_location(
+    conn: &Connection,
+    path: &OwnedObjectPath,
+    threshold_km: f64,
+    tx: &UnboundedSender<(f64, f64)>,
+    last: &mut Option<(f64, f64)>,
+) -> Result<()> {
+    let location = Proxy::new(
+        conn,
+        SERVICE,
+        path.as_str(),
+        "org.freedesktop.GeoClue2.Location",
+    )
+    .await?;
+    let lat: f64 = location.get_property("Latitude").await?;
+    let lon: f64 = location.get_property("Longitude").await?;
+    if last.is_some_and(|prev| distance_km(prev, (lat, lon)) < threshold_km) {
+        return Ok(());
+    }
+    *last = Some((lat, lon));
+    tx.send((lat, lon))
+        .map_err(|_| anyhow!("location receiver closed"))
 }
@@ -1,10 +1,40 @@
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
+use std::fs::DirBuilder;
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::UnixListener;
+use tokio::sync::watch;
 
 use crate::cli::ModeArg;
+use crate::color::{Gamma, validate_brightness, validate_temperature_range};
 use crate::scheduling::DayPhase;
+use crate::solar::Polar;
+
+/// Requests from IPC clients to the daemon loop, which owns the applied
+/// state and acts on them immediately.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Control {
+    SetMode(ModeArg),
+    SetTemperature { low: i32, high: i32 },
+    SetBrightness(f64),
+    SetGamma(Gamma),
+}
+
+pub type ControlSender = tokio::sync::mpsc::UnboundedSender<Control>;
+
+/// Everything an IPC connection needs to serve commands.
+#[derive(Clone)]
+pub struct IpcContext {
+    pub shared_state: Arc<Mutex<SharedAppState>>,
+    pub control_tx: ControlSender,
+    /// Latest published status, for subscribers.
+    pub status_rx: watch::Receiver<IpcResponse>,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -15,10 +45,22 @@ pub enum IpcCommand {
     GetStatus,
     #[serde(rename = "set_temperature")]
     SetTemperature { low: i32, high: i32 },
+    #[serde(rename = "set_brightness")]
+    SetBrightness { brightness: f64 },
+    /// `gamma` is a number or an `"R:G:B"` string
+    #[serde(rename = "set_gamma")]
+    SetGamma { gamma: Gamma },
+    #[serde(rename = "toggle")]
+    Toggle,
+    /// Replies with the current status, then streams a status line whenever
+    /// it changes for as long as the connection stays open.
+    #[serde(rename = "subscribe")]
+    Subscribe,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type")]
+#[allow(clippy::large_enum_variant)]
 pub enum IpcResponse {
     #[serde(rename = "status")]
     Status {
@@ -28,13 +70,36 @@ pub enum IpcResponse {
         current_temp: i32,
         low_temp: i32,
         high_temp: i32,
+        gamma: Gamma,
+        /// Brightness applied before per-output profiles
+        current_brightness: f64,
+        /// Level set with `set_brightness`
+        brightness: f64,
         location: Option<(f64, f64)>,
         sun_times: Option<(String, String)>,
+        /// `polar_day` or `polar_night` when the sun neither rises nor sets
+        polar: Option<String>,
+        next_transition: Option<String>,
+        override_expires: Option<String>,
+        outputs: Vec<OutputStatus>,
     },
     #[serde(rename = "error")]
     Error { message: String },
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutputStatus {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub enabled: bool,
+    pub temperature: Option<i32>,
+    pub gamma: Option<Gamma>,
+    pub brightness: Option<f64>,
+    /// Gamma tables sent to the output; unchanged tables are not resent
+    #[serde(default)]
+    pub uploads: u64,
+}
+
 #[derive(Debug, Clone)]
 pub struct SharedAppState {
     pub requested_mode: ModeArg,
@@ -43,8 +108,15 @@ pub struct SharedAppState {
     pub current_temp: i32,
     pub low_temp: i32,
     pub high_temp: i32,
+    pub gamma: Gamma,
+    pub current_brightness: f64,
+    pub brightness: f64,
     pub location: Option<(f64, f64)>,
     pub sun_times: Option<(String, String)>,
+    pub polar: Option<Polar>,
+    pub next_transition: Option<String>,
+    pub override_expires: Option<String>,
+    pub outputs: Vec<OutputStatus>,
 }
 
 impl SharedAppState {
@@ -56,98 +128,303 @@ impl SharedAppState {
             current_temp: (low_temp + high_temp) / 2,
             low_temp,
             high_temp,
+            gamma: Gamma::IDENTITY,
+            current_brightness: 1.0,
+            brightness: 1.0,
             location: None,
             sun_times: None,
+            polar: None,
+            next_transition: None,
+            override_expires: None,
+            outputs: Vec::new(),
         }
     }
 }
 
-fn format_status_response(state: &SharedAppState) -> IpcResponse {
-    let current = match state.current_mode {
-        DayPhase::Night => "night",
-        DayPhase::Sunrise => "sunrise",
-        DayPhase::Day => "day",
-        DayPhase::Sunset => "sunset",
-    };
-    let automatic = match state.automatic_mode {
-        DayPhase::Night => "night",
-        DayPhase::Sunrise => "sunrise",
-        DayPhase::Day => "day",
-        DayPhase::Sunset => "sunset",
-    };
+pub fn format_status_response(state: &SharedAppState) -> IpcResponse {
     IpcResponse::Status {
-        requested_mode: format!("{:?}", state.requested_mode).to_lowercase(),
-        current_mode: current.to_string(),
-        automatic_mode: automatic.to_string(),
+        requested_mode: state.requested_mode.name().to_string(),
+        current_mode: state.current_mode.name().to_string(),
+        automatic_mode: state.automatic_mode.name().to_string(),
         current_temp: state.current_temp,
         low_temp: state.low_temp,
         high_temp: state.high_temp,
+        gamma: state.gamma,
+        current_brightness: state.current_brightness,
+        brightness: state.brightness,
         location: state.location,
         sun_times: state.sun_times.clone(),
+        polar: state.polar.map(|p| p.name().to_string()),
+        next_transition: state.next_transition.clone(),
+        override_expires: state.override_expires.clone(),
+        outputs: state.outputs.clone(),
+    }
+}
+
+fn request_mode(state: &mut SharedAppState, mode: ModeArg, control_tx: &ControlSender) {
+    state.requested_mode = mode;
+    if let Err(e) = control_tx.send(Control::SetMode(mode)) {
+        eprintln!("Failed to send mode change: {}", e);
+    }
+}
+
+/// Flips between day and night relative to what is currently shown. When the
+/// automatic schedule already shows the target, the override is dropped
+/// instead so the daemon returns to auto.
+fn toggled_mode(state: &SharedAppState) -> ModeArg {
+    let (target, target_phase) = match state.current_mode {
+        DayPhase::Night | DayPhase::Sunset => (ModeArg::Day, DayPhase::Day),
+        DayPhase::Day | DayPhase::Sunrise => (ModeArg::Night, DayPhase::Night),
+    };
+    if state.requested_mode != ModeArg::Auto && state.automatic_mode == target_phase {
+        ModeArg::Auto
+    } else {
+        target
+    }
+}
+
+/// Publishes the status to subscribers if it differs from the last one sent.
+pub fn publish_status(state: &SharedAppState, status_tx: &watch::Sender<IpcResponse>) {
+    let status = format_status_response(state);
+    status_tx.send_if_modified(|current| {
+        if *current == status {
+            return false;
+        }
+        *current = status;
+        true
+    });
+}
+
+pub fn handle_command(command: IpcCommand, ctx: &IpcContext) -> IpcResponse {
+    let shared_state = &ctx.shared_state;
+    let control_tx = &ctx.control_tx;
+    match command {
+        IpcCommand::GetStatus | IpcCommand::Subscribe => {
+            let state = shared_state.lock().unwrap();
+            format_status_response(&state)
+        }
+        IpcCommand::SetMode { mode } => {
+            eprintln!("Setting mode to: {}", mode);
+            let new_mode = match mode.as_str() {
+                "auto" => ModeArg::Auto,
+                "day" => ModeArg::Day,
+                "night" => ModeArg::Night,
+                "sunset" => ModeArg::Sunset,
+                _ => {
+                    return IpcResponse::Error {
+                        message: format!("Unknown mode: {}", mode),
+                    };
+                }
+            };
+            let mut state = shared_state.lock().unwrap();
+            request_mode(&mut state, new_mode, control_tx);
+            format_status_response(&state)
+        }
+        IpcCommand::Toggle => {
+            let mut state = shared_state.lock().unwrap();
+            let new_mode = toggled_mode(&state);
+            eprintln!("Toggling mode to: {:?}", new_mode);
+            request_mode(&mut state, new_mode, control_tx);
+            format_status_response(&state)
+        }
+        IpcCommand::SetTemperature { low, high } => {
+            if let Err(e) = validate_temperature_range(low, high) {
+                return IpcResponse::Error {
+                    message: format!("Invalid temperature range: {}", e),
+                };
+            }
+            eprintln!("Setting temperature: {} - {}", low, high);
+            let mut state = shared_state.lock().unwrap();
+            state.low_temp = low;
+            state.high_temp = high;
+            if let Err(e) = control_tx.send(Control::SetTemperature { low, high }) {
+                eprintln!("Failed to send temperature change: {}", e);
+            }
+            format_status_response(&state)
+        }
+        IpcCommand::SetBrightness { brightness } => {
+            if let Err(e) = validate_brightness(brightness) {
+                return IpcResponse::Error {
+                    message: format!("Invalid brightness: {}", e),
+                };
+            }
+            eprintln!("Setting brightness: {}", brightness);
+            let mut state = shared_state.lock().unwrap();
+            state.brightness = brightness;
+            if let Err(e) = control_tx.send(Control::SetBrightness(brightness)) {
+                eprintln!("Failed to send brightness change: {}", e);
+            }
+            format_status_response(&state)
+        }
+        IpcCommand::SetGamma { gamma } => {
+            // Already validated when deserialized, but not over D-Bus
+            if let Err(e) = gamma.validate() {
+                return IpcResponse::Error {
+                    message: format!("Invalid gamma: {}", e),
+                };
+            }
+            eprintln!("Setting gamma: {}", gamma);
+            let mut state = shared_state.lock().unwrap();
+            state.gamma = gamma;
+            if let Err(e) = control_tx.send(Control::SetGamma(gamma)) {
+                eprintln!("Failed to send gamma change: {}", e);
+            }
+            format_status_response(&state)
+        }
+    }
+}
+
+/// Waits for the next status change on a subscription. Never resolves while
+/// the connection is not subscribed.
+async fn next_status(
+    subscription: &mut Option<watch::Receiver<IpcResponse>>,
+) -> Option<IpcResponse> {
+    match subscription {
+        Some(rx) => {
+            rx.changed().await.ok()?;
+            Some(rx.borrow_and_update().clone())
+        }
+        None => std::future::pending().await,
     }
 }
 
-pub async fn handle_stdin_commands(
-    shared_state: Arc<Mutex<SharedAppState>>,
-    mode_tx: tokio::sync::mpsc::UnboundedSender<ModeArg>,
+async fn write_response<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    response: &IpcResponse,
 ) -> Result<()> {
-    let stdin = tokio::io::stdin();
-    let mut reader = BufReader::new(stdin);
-    let mut stdout = tokio::io::stdout();
-    let mut line = String::new();
+    let response_json = serde_json::to_string(response)?;
+    writer.write_all(response_json.as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    writer.flush().await?;
+    Ok(())
+}
 
+/// Serves JSONL commands from `reader`, writing one response line per
+/// command to `writer` until EOF.
+async fn serve_lines<R, W>(reader: R, mut writer: W, ctx: IpcContext) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut lines = reader.lines();
+    let mut subscription = None;
     loop {
-        line.clear();
-        match reader.read_line(&mut line).await {
-            Ok(0) => break, // EOF
-            Ok(_) => {
-                let response = match serde_json::from_str::<IpcCommand>(&line.trim()) {
-                    Ok(IpcCommand::GetStatus) => {
-                        let state = shared_state.lock().unwrap();
-                        format_status_response(&state)
-                    }
-                    Ok(IpcCommand::SetMode { mode }) => {
-                        eprintln!("Setting mode to: {}", mode);
-                        let mut state = shared_state.lock().unwrap();
-                        let new_mode = match mode.as_str() {
-                            "auto" => ModeArg::Auto,
-                            "day" => ModeArg::Day,
-                            "night" => ModeArg::Night,
-                            "sunset" => ModeArg::Sunset,
-                            _ => state.requested_mode,
-                        };
-                        state.requested_mode = new_mode;
-
-                        if let Err(e) = mode_tx.send(new_mode) {
-                            eprintln!("Failed to send mode change: {}", e);
+        tokio::select! {
+            line = lines.next_line() => {
+                let Some(line) = line? else {
+                    break;
+                };
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let response = match serde_json::from_str::<IpcCommand>(line) {
+                    Ok(command) => {
+                        if matches!(command, IpcCommand::Subscribe) {
+                            let mut rx = ctx.status_rx.clone();
+                            rx.mark_unchanged();
+                            subscription = Some(rx);
                         }
-
-                        format_status_response(&state)
-                    }
-                    Ok(IpcCommand::SetTemperature { low, high }) => {
-                        eprintln!("Setting temperature: {} - {}", low, high);
-                        let mut state = shared_state.lock().unwrap();
-                        state.low_temp = low;
-                        state.high_temp = high;
-                        state.current_temp = (low + high) / 2;
-                        format_status_response(&state)
+                        handle_command(command, &ctx)
                     }
                     Err(e) => IpcResponse::Error {
                         message: format!("Invalid command: {}", e),
                     },
                 };
+                write_response(&mut writer, &response).await?;
+            }
+            Some(status) = next_status(&mut subscription) => {
+                write_response(&mut writer, &status).await?;
+            }
+        }
+    }
+    Ok(())
+}
 
-                let response_json = serde_json::to_string(&response)?;
-                stdout.write_all(response_json.as_bytes()).await?;
-                stdout.write_all(b"\n").await?;
-                stdout.flush().await?;
+pub async fn handle_stdin_commands(ctx: IpcContext) -> Result<()> {
+    let reader = BufReader::new(tokio::io::stdin());
+    serve_lines(reader, tokio::io::stdout(), ctx).await
+}
+
+/// Binds the IPC socket, replacing a stale socket left behind by a daemon
+/// that did not shut down cleanly. Fails if another instance is listening.
+pub fn bind_socket(path: &Path) -> Result<UnixListener> {
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(anyhow!("{} exists and is not a socket", path.display()));
+        }
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(anyhow!(
+                    "another redland instance is listening on {}",
+                    path.display()
+                ));
+            }
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+                eprintln!("Removing stale socket {}", path.display());
+                std::fs::remove_file(path)
+                    .with_context(|| format!("remove stale socket {}", path.display()))?;
             }
             Err(e) => {
-                eprintln!("Error reading from stdin: {}", e);
-                break;
+                return Err(e).with_context(|| format!("probe socket {}", path.display()));
             }
         }
     }
-    Ok(())
+    // Bind inside a private directory and move the socket into place once it
+    // is owner-only, so it is never connectable with umask permissions
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let staging = dir.join(format!(".redland-{}", std::process::id()));
+    DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .with_context(|| format!("create {}", staging.display()))?;
+    let result = bind_staged(&staging.join("socket"), path);
+    let _ = std::fs::remove_dir_all(&staging);
+    result
+}
+
+fn bind_staged(staged: &Path, path: &Path) -> Result<UnixListener> {
+    let listener =
+        UnixListener::bind(staged).with_context(|| format!("bind {}", staged.display()))?;
+    std::fs::set_permissions(staged, std::fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("chmod {}", staged.display()))?;
+    std::fs::rename(staged, path).with_context(|| format!("move socket to {}", path.display()))?;
+    Ok(listener)
+}
+
+pub async fn serve_socket(listener: UnixListener, ctx: IpcContext) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            let (read_half, write_half) = stream.into_split();
+            let reader = BufReader::new(read_half);
+            if let Err(e) = serve_lines(reader, write_half, ctx).await {
+                eprintln!("IPC client error: {}", e);
+            }
+        });
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn socket_is_owner_only_and_replaces_stale_ones() {
+        let dir = std::env::temp_dir().join(format!("redland-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("redland.sock");
+        let _ = std::fs::remove_file(&path);
+
+        let listener = bind_socket(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(bind_socket(&path).is_err(), "bound while another listens");
+
+        drop(listener);
+        let listener = bind_socket(&path).unwrap();
+        drop(listener);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
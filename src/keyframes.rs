@@ -0,0 +1,303 @@
+use anyhow::{Context, Result, anyhow};
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::fmt;
+
+use crate::color::{validate_brightness, validate_temperature};
+use clap::ValueEnum;
+
+use crate::scheduling::{DayStops, Easing, local_timestamp, parse_hhmm};
+
+/// Point of the day a keyframe is pinned to.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Anchor {
+    /// Local wall-clock time, seconds after midnight
+    Time(i64),
+    /// Start of the morning fade
+    Dawn,
+    Sunrise,
+    Sunset,
+    /// End of the evening fade
+    Dusk,
+}
+
+/// An anchor shifted by a number of seconds.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyTime {
+    pub anchor: Anchor,
+    pub offset: i64,
+}
+
+impl fmt::Display for KeyTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.anchor {
+            Anchor::Time(s) => write!(f, "{:02}:{:02}", s / 3600, s % 3600 / 60)?,
+            Anchor::Dawn => f.write_str("dawn")?,
+            Anchor::Sunrise => f.write_str("sunrise")?,
+            Anchor::Sunset => f.write_str("sunset")?,
+            Anchor::Dusk => f.write_str("dusk")?,
+        }
+        if self.offset == 0 {
+            return Ok(());
+        }
+        let abs = self.offset.abs();
+        f.write_str(if self.offset < 0 { "-" } else { "+" })?;
+        for (value, unit) in [(abs / 3600, "h"), (abs % 3600 / 60, "m"), (abs % 60, "s")] {
+            if value > 0 {
+                write!(f, "{value}{unit}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Temperature of a keyframe, either absolute or one end of the configured
+/// range so it follows `set_temperature`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Level {
+    Low,
+    High,
+    Kelvin(i32),
+}
+
+impl Level {
+    pub fn kelvin(self, low: i32, high: i32) -> i32 {
+        match self {
+            Level::Low => low,
+            Level::High => high,
+            Level::Kelvin(k) => k,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub at: KeyTime,
+    pub temp: Level,
+    pub brightness: f64,
+    /// Easing of the transition to the next keyframe, if not the default
+    pub ease: Option<Easing>,
+}
+
+const fn keyframe(anchor: Anchor, temp: Level) -> Keyframe {
+    Keyframe {
+        at: KeyTime { anchor, offset: 0 },
+        temp,
+        brightness: 1.0,
+        ease: None,
+    }
+}
+
+/// Two plateaus joined by ramps from dawn to sunrise and sunset to dusk.
+pub const DEFAULT_KEYFRAMES: &[Keyframe] = &[
+    keyframe(Anchor::Dawn, Level::Low),
+    keyframe(Anchor::Sunrise, Level::High),
+    keyframe(Anchor::Sunset, Level::High),
+    keyframe(Anchor::Dusk, Level::Low),
+];
+
+/// A keyframe placed on a particular day.
+#[derive(Copy, Clone, Debug)]
+pub struct Point {
+    pub at: i64,
+    pub temp: Level,
+    pub brightness: f64,
+    pub ease: Option<Easing>,
+}
+
+/// Places `keyframes` on `date`, whose sun events are `stops`.
+pub fn resolve(keyframes: &[Keyframe], date: NaiveDate, stops: DayStops) -> Result<Vec<Point>> {
+    keyframes
+        .iter()
+        .map(|k| {
+            let base = match k.at.anchor {
+                Anchor::Time(seconds) => local_timestamp(date, seconds)?,
+                Anchor::Dawn => stops.dawn,
+                Anchor::Sunrise => stops.sunrise,
+                Anchor::Sunset => stops.sunset,
+                Anchor::Dusk => stops.night,
+            };
+            Ok(Point {
+                at: base + k.at.offset,
+                temp: k.temp,
+                brightness: k.brightness,
+                ease: k.ease,
+            })
+        })
+        .collect()
+}
+
+/// Parses `HH:MM` or `dawn`, `sunrise`, `sunset`, `dusk`, optionally followed
+/// by an offset such as `+30m` or `-1h15m`.
+pub fn parse_key_time(s: &str) -> Result<KeyTime> {
+    let (anchor, offset) = match s.find(['+', '-']) {
+        Some(i) => (&s[..i], parse_offset(&s[i..])?),
+        None => (s, 0),
+    };
+    let anchor = match anchor {
+        "dawn" => Anchor::Dawn,
+        "sunrise" => Anchor::Sunrise,
+        "sunset" => Anchor::Sunset,
+        "dusk" => Anchor::Dusk,
+        time => Anchor::Time(parse_hhmm(time).with_context(|| {
+            format!("expected HH:MM, dawn, sunrise, sunset or dusk, got {time:?}")
+        })?),
+    };
+    Ok(KeyTime { anchor, offset })
+}
+
+/// Parses a signed offset made of `h`, `m` and `s` parts, e.g. `-1h30m`.
+fn parse_offset(s: &str) -> Result<i64> {
+    let (sign, mut rest) = match s.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(anyhow!("offset must start with + or -")),
+    };
+    if rest.is_empty() {
+        return Err(anyhow!("empty offset"));
+    }
+    let mut total = 0;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| anyhow!("offset {s:?} is missing a unit (h, m or s)"))?;
+        let value: i64 = rest[..digits]
+            .parse()
+            .with_context(|| format!("invalid offset {s:?}"))?;
+        let mut chars = rest[digits..].chars();
+        let unit = match chars.next() {
+            Some('h') => 3600,
+            Some('m') => 60,
+            Some('s') => 1,
+            u => return Err(anyhow!("unknown unit {u:?} in offset {s:?}")),
+        };
+        total += value * unit;
+        rest = chars.as_str();
+    }
+    Ok(sign * total)
+}
+
+/// Parses `low`, `high` or a temperature in kelvin.
+pub fn parse_level(s: &str) -> Result<Level> {
+    match s {
+        "low" => Ok(Level::Low),
+        "high" => Ok(Level::High),
+        _ => {
+            let k = s
+                .parse()
+                .map_err(|_| anyhow!("expected low, high or a temperature, got {s:?}"))?;
+            validate_temperature(k)?;
+            Ok(Level::Kelvin(k))
+        }
+    }
+}
+
+/// Parses `AT:key=value,...`, e.g. `sunset+30m:temp=4500,brightness=0.9`
+/// (keys: temp, brightness, ease).
+pub fn parse_keyframe_spec(spec: &str) -> Result<Keyframe> {
+    let (at, settings) = spec
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("expected AT:temp=K,..."))?;
+    let at = parse_key_time(at)?;
+    let mut temp = None;
+    let mut brightness = 1.0;
+    let mut ease = None;
+    for setting in settings.split(',').filter(|s| !s.is_empty()) {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected key=value, got {setting:?}"))?;
+        match key {
+            "temp" => temp = Some(parse_level(value)?),
+            "brightness" => {
+                brightness = value
+                    .parse()
+                    .with_context(|| format!("invalid brightness {value:?}"))?;
+                validate_brightness(brightness)?;
+            }
+            "ease" => ease = Some(Easing::from_str(value, false).map_err(|e| anyhow!(e))?),
+            _ => return Err(anyhow!("unknown keyframe key {key:?}")),
+        }
+    }
+    let temp = temp.ok_or_else(|| anyhow!("keyframe {spec:?} is missing temp"))?;
+    Ok(Keyframe {
+        at,
+        temp,
+        brightness,
+        ease,
+    })
+}
+
+/// Days a [`Rule`] applies to. Both lists must match when both are set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub name: Option<String>,
+    pub weekdays: Vec<Weekday>,
+    /// Inclusive date ranges
+    pub dates: Vec<(NaiveDate, NaiveDate)>,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl Rule {
+    fn matches(&self, date: NaiveDate) -> bool {
+        (self.weekdays.is_empty() || self.weekdays.contains(&date.weekday()))
+            && (self.dates.is_empty()
+                || self
+                    .dates
+                    .iter()
+                    .any(|(first, last)| (*first..=*last).contains(&date)))
+    }
+}
+
+/// Base keyframes plus rules replacing them on some days.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Schedule {
+    /// Empty for the default schedule
+    pub keyframes: Vec<Keyframe>,
+    /// Checked in order; the first matching rule wins
+    pub rules: Vec<Rule>,
+}
+
+impl Schedule {
+    /// The rule in effect on `date`, if any.
+    pub fn rule_on(&self, date: NaiveDate) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(date))
+    }
+
+    /// Keyframes configured for `date`; empty for the default schedule.
+    fn configured_on(&self, date: NaiveDate) -> &[Keyframe] {
+        self.rule_on(date)
+            .map_or(&self.keyframes, |rule| &rule.keyframes)
+    }
+
+    /// Keyframes for `date`, falling back to the default schedule.
+    pub fn keyframes_on(&self, date: NaiveDate) -> &[Keyframe] {
+        match self.configured_on(date) {
+            [] => DEFAULT_KEYFRAMES,
+            keyframes => keyframes,
+        }
+    }
+
+    /// Whether `date` uses the default schedule.
+    pub fn is_default_on(&self, date: NaiveDate) -> bool {
+        self.configured_on(date).is_empty()
+    }
+}
+
+/// Parses a weekday such as `fri` or `Friday`.
+pub fn parse_weekday(s: &str) -> Result<Weekday> {
+    s.parse()
+        .map_err(|_| anyhow!("expected a weekday such as mon or friday, got {s:?}"))
+}
+
+/// Parses `YYYY-MM-DD` or an inclusive range `YYYY-MM-DD..YYYY-MM-DD`.
+pub fn parse_date_range(s: &str) -> Result<(NaiveDate, NaiveDate)> {
+    let (first, last) = s.split_once("..").unwrap_or((s, s));
+    let parse = |d: &str| {
+        NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d")
+            .with_context(|| format!("expected YYYY-MM-DD, got {d:?}"))
+    };
+    let (first, last) = (parse(first)?, parse(last)?);
+    if last < first {
+        return Err(anyhow!("date range {s:?} ends before it starts"));
+    }
+    Ok((first, last))
+}
@@ -0,0 +1,150 @@
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+use crate::config::Settings;
+use crate::geoclue;
+use crate::persist::PersistedState;
+use crate::timezone;
+
+/// How long to wait at startup for GeoClue's first location.
+pub const GEOCLUE_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Sunrise and sunset used when no location source yields coordinates.
+pub const FIXED_TIMES: (i64, i64) = (6 * 3600, 18 * 3600);
+
+/// Where coordinates may come from, tried in the configured order.
+#[derive(Copy, Clone, Debug, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocationSource {
+    /// `lat`/`lon` from the command line or config file
+    Explicit,
+    /// Last location reported by GeoClue
+    Cache,
+    /// GeoClue2, followed for as long as the daemon runs
+    Geoclue,
+    /// Rough estimate from the configured IANA timezone
+    Timezone,
+    /// Fixed sunrise and sunset times
+    Fixed,
+}
+
+pub const DEFAULT_SOURCES: &[LocationSource] = &[
+    LocationSource::Explicit,
+    LocationSource::Cache,
+    LocationSource::Geoclue,
+    LocationSource::Timezone,
+    LocationSource::Fixed,
+];
+
+impl fmt::Display for LocationSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LocationSource::Explicit => "explicit",
+            LocationSource::Cache => "cache",
+            LocationSource::Geoclue => "geoclue",
+            LocationSource::Timezone => "timezone",
+            LocationSource::Fixed => "fixed",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Whether GeoClue is in the chain with nothing definite before it. It is
+/// then followed even if an earlier source such as the cache answered, so a
+/// stale location gets refreshed.
+pub fn uses_geoclue(settings: &Settings) -> bool {
+    if settings.manual.is_some() {
+        return false;
+    }
+    for source in &settings.location_sources {
+        match source {
+            LocationSource::Explicit if settings.location.is_some() => return false,
+            LocationSource::Geoclue => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Starts following GeoClue if the settings call for it.
+pub fn spawn_tracker(
+    settings: &Settings,
+    location_tx: &UnboundedSender<(f64, f64)>,
+) -> Option<JoinHandle<()>> {
+    if !uses_geoclue(settings) {
+        return None;
+    }
+    eprintln!("Tracking location via GeoClue...");
+    let threshold = settings.location_threshold;
+    let tx = location_tx.clone();
+    Some(tokio::spawn(async move {
+        geoclue::track(geoclue::DESKTOP_ID, threshold, tx).await
+    }))
+}
+
+/// Saves a location reported by GeoClue for the next start.
+pub fn remember(location: (f64, f64)) {
+    if let Err(e) = PersistedState::update(|state| state.location = Some(location)) {
+        eprintln!("Failed to cache location: {e:#}");
+    }
+}
+
+/// Walks the source chain and returns the first location found, or `None`
+/// to use fixed times. The GeoClue step waits up to `geoclue_wait` for the
+/// tracker's first report on `updates`.
+pub async fn resolve(
+    settings: &Settings,
+    updates: &mut UnboundedReceiver<(f64, f64)>,
+    geoclue_wait: Duration,
+) -> Result<Option<(f64, f64)>> {
+    if settings.manual.is_some() {
+        return Ok(None);
+    }
+    for &source in &settings.location_sources {
+        let found = match source {
+            LocationSource::Explicit => settings.location,
+            LocationSource::Cache => PersistedState::load().location,
+            LocationSource::Geoclue => {
+                let found = tokio::time::timeout(geoclue_wait, updates.recv())
+                    .await
+                    .ok()
+                    .flatten();
+                if let Some(location) = found {
+                    remember(location);
+                }
+                found
+            }
+            LocationSource::Timezone => timezone::estimate().map(|(zone, location)| {
+                eprintln!("Estimating location from timezone {zone}");
+                location
+            }),
+            LocationSource::Fixed => {
+                let (sunrise, sunset) = FIXED_TIMES;
+                eprintln!(
+                    "No location available; using fixed times {:02}:00-{:02}:00",
+                    sunrise / 3600,
+                    sunset / 3600
+                );
+                return Ok(None);
+            }
+        };
+        if let Some((lat, lon)) = found {
+            eprintln!("Location from {source}: {lat:.4}, {lon:.4}");
+            return Ok(found);
+        }
+    }
+    Err(anyhow!(
+        "no location source provided a location (tried {})",
+        settings
+            .location_sources
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
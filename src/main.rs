@@ -1,77 +1,168 @@
+mod bar;
+mod cities;
 mod cli;
 mod color;
+mod config;
+mod ctl;
+mod dbus;
 mod geoclue;
 mod ipc;
+mod keyframes;
+mod location;
+mod outputs;
+mod paths;
+mod persist;
+mod preview;
 mod scheduling;
+mod solar;
+mod timezone;
 mod wayland;
 
 use anyhow::{Context, Result, anyhow};
 use chrono::Local;
 use clap::Parser;
 use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use std::os::fd::{AsFd, AsRawFd};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::Interest;
+use tokio::io::unix::AsyncFd;
 use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::watch;
 use wayland_client::Connection;
 
-use cli::{ModeArg, Opts};
-use geoclue::geoclue_lat_lon;
-use ipc::SharedAppState;
+use cli::{Command, ModeArg, Opts};
+use color::{floored_brightness, validate_brightness, validate_temperature_range};
+use config::{Settings, config_path, watch_config};
+use ipc::{Control, IpcContext, SharedAppState};
+use persist::PersistedState;
 use scheduling::{
-    DayPhase, TrayOverride, compute_day_stops, next_sunrise_timestamp, parse_hhmm, phase_for,
-    temperature_for,
+    DayPhase, FADE_FRAME, Fade, SunSchedule, TrayOverride, compute_day_stops, evaluate,
+    format_hhmm, next_sunrise_timestamp, next_transition_timestamp, next_update,
 };
 use wayland::{AppState, set_temperature_all};
 
+/// A value to start with: one saved by a previous run wins over the config
+/// file, but not over an explicit command-line flag. `saved` picks it out
+/// of the persisted state, or drops it if invalid.
+fn restore<T>(
+    settings: &Settings,
+    explicit: bool,
+    configured: T,
+    saved: impl FnOnce(PersistedState) -> Option<T>,
+) -> T {
+    if !settings.persist || explicit {
+        return configured;
+    }
+    saved(PersistedState::load()).unwrap_or(configured)
+}
+
+/// Saves a value set at runtime for the next start, if `persist` is on.
+fn save(persist: bool, what: &str, update: impl FnOnce(&mut PersistedState)) {
+    if !persist {
+        return;
+    }
+    if let Err(e) = PersistedState::update(update) {
+        eprintln!("Failed to save {what}: {e:#}");
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let opts = Opts::parse();
-    let startup_mode: ModeArg = opts.mode;
-
-    if opts.high_temp <= opts.low_temp {
-        return Err(anyhow!("--high must be > --low"));
+    match &opts.command {
+        Some(Command::Ctl(args)) => std::process::exit(ctl::run(&opts, args)),
+        Some(Command::Status(args)) => std::process::exit(bar::run(&opts, args)),
+        Some(Command::Locate(args)) => std::process::exit(cities::run(args)),
+        Some(Command::Schedule(args)) => return preview::run(&opts, args).await,
+        None => {}
     }
+    let mut settings = Settings::load(&opts)?;
+    let startup_mode: ModeArg = settings.mode;
 
-    let manual = match (&opts.sunrise, &opts.sunset) {
-        (Some(a), Some(b)) => Some((parse_hhmm(a)?, parse_hhmm(b)?)),
-        (None, None) => None,
-        _ => return Err(anyhow!("Provide both --sunrise and --sunset or neither")),
-    };
-
-    let (lat, lon) = match manual {
-        Some(_) => (0.0, 0.0),
-        None => {
-            let lat = opts.latitude;
-            let lon = opts.longitude;
-            match (lat, lon) {
-                (Some(a), Some(b)) => (a, b),
-                _ => {
-                    eprintln!("Resolving location via GeoClue...");
-                    geoclue_lat_lon("wlsunset-rs.desktop").context("GeoClue failed")?
-                }
-            }
-        }
-    };
+    let (location_tx, mut location_rx) = tokio::sync::mpsc::unbounded_channel::<(f64, f64)>();
+    let mut location_tracker = location::spawn_tracker(&settings, &location_tx);
+    let mut location =
+        location::resolve(&settings, &mut location_rx, location::GEOCLUE_TIMEOUT).await?;
+    let (mut low_temp, mut high_temp) = restore(
+        &settings,
+        opts.low_temp.is_some() || opts.high_temp.is_some(),
+        (settings.low_temp, settings.high_temp),
+        |saved| {
+            saved
+                .temperature
+                .filter(|&(low, high)| validate_temperature_range(low, high).is_ok())
+        },
+    );
+    let mut brightness_level = restore(
+        &settings,
+        opts.brightness.is_some(),
+        settings.brightness,
+        |saved| {
+            saved
+                .brightness
+                .filter(|&level| validate_brightness(level).is_ok())
+        },
+    );
+    let mut gamma = restore(&settings, opts.gamma.is_some(), settings.gamma, |saved| {
+        saved.gamma.filter(|gamma| gamma.validate().is_ok())
+    });
 
-    let shared_state = Arc::new(Mutex::new(SharedAppState::new(
-        opts.low_temp,
-        opts.high_temp,
-    )));
+    let shared_state = Arc::new(Mutex::new(SharedAppState::new(low_temp, high_temp)));
     {
         let mut state = shared_state.lock().unwrap();
         state.requested_mode = startup_mode;
+        state.brightness = brightness_level;
+        state.gamma = gamma;
     }
 
-    let (mode_tx, mut mode_rx) = tokio::sync::mpsc::unbounded_channel::<ModeArg>();
+    let (control_tx, mut control_rx) = tokio::sync::mpsc::unbounded_channel::<Control>();
+
+    let (status_tx, status_rx) =
+        watch::channel(ipc::format_status_response(&shared_state.lock().unwrap()));
+    let ipc_ctx = IpcContext {
+        shared_state: Arc::clone(&shared_state),
+        control_tx,
+        status_rx,
+    };
 
     // Spawn stdin reader for JSONL IPC
-    let shared_state_clone = Arc::clone(&shared_state);
+    let stdin_ctx = ipc_ctx.clone();
     tokio::spawn(async move {
-        if let Err(e) = ipc::handle_stdin_commands(shared_state_clone, mode_tx).await {
+        if let Err(e) = ipc::handle_stdin_commands(stdin_ctx).await {
             eprintln!("Stdin handler error: {}", e);
         }
     });
 
+    // Unix socket for any number of clients, alongside stdin
+    match &settings.socket {
+        Some(path) => {
+            let listener = ipc::bind_socket(path)?;
+            eprintln!("Listening on {}", path.display());
+            let socket_ctx = ipc_ctx.clone();
+            tokio::spawn(async move {
+                if let Err(e) = ipc::serve_socket(listener, socket_ctx).await {
+                    eprintln!("Socket server error: {}", e);
+                }
+            });
+        }
+        None => eprintln!("XDG_RUNTIME_DIR is not set; IPC socket disabled"),
+    }
+
+    // D-Bus service; a missing session bus or a taken name is not fatal
+    if settings.dbus {
+        let dbus_ctx = ipc_ctx.clone();
+        tokio::spawn(async move {
+            if let Err(e) = dbus::serve(dbus_ctx).await {
+                eprintln!("D-Bus service disabled: {:#}", e);
+            }
+        });
+    }
+
     let mut tray_override: Option<TrayOverride> = None;
+    let mut fade: Option<Fade> = None;
+    // Temperature and brightness last sent to the outputs
+    let mut shown: Option<(i32, f64)> = None;
     let mut initial_override_pending = if matches!(startup_mode, ModeArg::Day | ModeArg::Night) {
         Some(startup_mode)
     } else {
@@ -83,7 +174,7 @@ async fn main() -> Result<()> {
     let qh = event_queue.handle();
     conn.display().get_registry(&qh, ());
 
-    let mut state = AppState::new();
+    let mut state = AppState::new(settings.filter.clone(), settings.profiles.clone());
     event_queue
         .roundtrip(&mut state)
         .context("initial wayland roundtrip")?;
@@ -96,29 +187,48 @@ async fn main() -> Result<()> {
         .context("gamma setup roundtrip")?;
 
     let mut sigusr1 = signal(SignalKind::user_defined1()).context("setup SIGUSR1 handler")?;
+    // Wake up on Wayland events too, so hotplugged outputs get their gamma
+    // applied as soon as the compositor reports their ramp size.
+    let wayland_fd = AsyncFd::with_interest(conn.as_fd().as_raw_fd(), Interest::READABLE)
+        .context("register wayland fd")?;
+
+    let mut sighup = signal(SignalKind::hangup()).context("setup SIGHUP handler")?;
+    let (reload_tx, mut reload_rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+    if let Some(path) = config_path(&opts)
+        && let Err(e) = watch_config(&path, reload_tx)
+    {
+        eprintln!("Not watching config file for changes: {e:#}");
+    }
 
     loop {
         event_queue
             .dispatch_pending(&mut state)
             .context("dispatch pending")?;
 
-        let now = Local::now().timestamp();
-        let stops = compute_day_stops(now, lat, lon, opts.duration, manual)?;
-        let mut temp = temperature_for(now, stops, opts.low_temp, opts.high_temp);
-        let natural_phase = phase_for(now, stops);
+        let clock = Local::now();
+        let now = clock.timestamp();
+        // Sub-second time for smooth transitions
+        let precise_now = clock.timestamp_millis() as f64 / 1000.0;
+        let sched = SunSchedule::new(&settings, location);
+        let stops = compute_day_stops(now, &sched)?;
+        let target = evaluate(precise_now, stops, &sched, low_temp, high_temp)?;
+        let natural_phase = target.phase;
+        let mut temp = target.temp;
+        let mut brightness = target.brightness * brightness_level;
         let mut applied_phase = natural_phase;
 
         if let Some(mode) = initial_override_pending.take() {
-            let expires_at = next_sunrise_timestamp(now, stops, lat, lon, opts.duration, manual)?;
+            let expires_at = next_sunrise_timestamp(now, stops, &sched)?;
             tray_override = Some(TrayOverride { mode, expires_at });
         }
 
-
-        let override_expired = tray_override
-            .as_ref()
-            .map_or(false, |state| now >= state.expires_at);
-        if override_expired {
-            tray_override = None;
+        if let Some(expired) = tray_override.take_if(|state| now >= state.expires_at) {
+            fade = Fade::start(shown, settings.fade);
+            // Back to auto, unless a newer mode request is already on its way
+            let mut shared = shared_state.lock().unwrap();
+            if shared.requested_mode == expired.mode {
+                shared.requested_mode = ModeArg::Auto;
+            }
         }
 
         if let Some(state) = tray_override.as_ref() {
@@ -126,66 +236,192 @@ async fn main() -> Result<()> {
                 ModeArg::Auto => {}
                 ModeArg::Day => {
                     applied_phase = DayPhase::Day;
-                    temp = opts.high_temp;
+                    temp = high_temp;
                 }
                 ModeArg::Night => {
                     applied_phase = DayPhase::Night;
-                    temp = opts.low_temp;
+                    temp = low_temp;
                 }
                 ModeArg::Sunset => {
                     applied_phase = DayPhase::Sunset;
-                    temp = (opts.low_temp + opts.high_temp) / 2;
+                    temp = (low_temp + high_temp) / 2;
                 }
             }
         }
 
+        let next_transition = next_transition_timestamp(now, stops, &sched)?;
+
+        if let Some(running) = fade {
+            match running.frame(temp, brightness) {
+                Some(frame) => (temp, brightness) = frame,
+                None => fade = None,
+            }
+        }
+        set_temperature_all(
+            &mut state.outputs,
+            temp,
+            low_temp,
+            high_temp,
+            gamma,
+            brightness,
+            settings.min_brightness,
+        );
+        shown = Some((temp, brightness));
+
         // Update shared state with current and automatic phases
         {
             let mut shared = shared_state.lock().unwrap();
             shared.current_mode = applied_phase;
             shared.automatic_mode = natural_phase;
             shared.current_temp = temp;
+            shared.low_temp = low_temp;
+            shared.high_temp = high_temp;
+            shared.gamma = gamma;
+            shared.current_brightness = floored_brightness(brightness, settings.min_brightness);
+            shared.brightness = brightness_level;
+            shared.location = location;
+            shared.sun_times = stops
+                .sun_times
+                .map(|(sunrise, sunset)| (format_hhmm(sunrise), format_hhmm(sunset)));
+            shared.polar = stops.polar;
+            shared.next_transition = next_transition.map(format_hhmm);
+            shared.override_expires = tray_override.as_ref().map(|o| format_hhmm(o.expires_at));
+            shared.outputs = state.output_statuses();
+            ipc::publish_status(&shared, &status_tx);
         }
-
-        set_temperature_all(&mut state.outputs, temp, 1.0);
         conn.flush().context("flush wayland connection")?;
 
-        let next = if now < stops.dawn {
-            stops.dawn
-        } else if now < stops.sunrise {
-            now + 10
-        } else if now < stops.sunset {
-            stops.sunset
-        } else if now < stops.night {
-            now + 10
-        } else {
-            ((now / 86400) + 1) * 86400
-        };
-        let wait_ms = ((next - now).max(1) * 1000) as i64;
+        let next = next_update(precise_now, stops, &sched, low_temp, high_temp)?;
+        let mut wait = Duration::from_secs_f64((next - precise_now).max(0.0));
+        if fade.is_some() {
+            wait = wait.min(FADE_FRAME);
+        }
 
+        let mut reload = false;
         tokio::select! {
             _ = sigusr1.recv() => {
                 // Signal received, continue loop
             }
-            Some(mode) = mode_rx.recv() => {
-                // Mode change received, process immediately
-                eprintln!("★ Received mode change from socket: {:?}", mode);
-                match mode {
-                    ModeArg::Auto => {
-                        tray_override = None;
+            _ = sighup.recv() => {
+                reload = true;
+            }
+            Some(()) = reload_rx.recv() => {
+                // Editors often emit several events per save
+                while reload_rx.try_recv().is_ok() {}
+                reload = true;
+            }
+            Some(control) = control_rx.recv() => {
+                match control {
+                    Control::SetMode(mode) => {
+                        // Mode change received, process immediately
+                        eprintln!("★ Received mode change from socket: {:?}", mode);
+                        match mode {
+                            ModeArg::Auto => {
+                                tray_override = None;
+                            }
+                            ModeArg::Day | ModeArg::Night | ModeArg::Sunset => {
+                                let expires_at =
+                                    next_sunrise_timestamp(now, stops, &sched)?;
+                                tray_override = Some(TrayOverride { mode, expires_at });
+                            }
+                        }
                     }
-                    ModeArg::Day | ModeArg::Night | ModeArg::Sunset => {
-                        let expires_at =
-                            next_sunrise_timestamp(now, stops, lat, lon, opts.duration, manual)?;
-                        tray_override = Some(TrayOverride { mode, expires_at });
+                    Control::SetTemperature { low, high } => {
+                        eprintln!("★ Received temperature range from socket: {low}-{high}");
+                        (low_temp, high_temp) = (low, high);
+                        save(settings.persist, "temperature range", |saved| {
+                            saved.temperature = Some((low, high))
+                        });
+                    }
+                    Control::SetBrightness(level) => {
+                        eprintln!("★ Received brightness from socket: {level}");
+                        brightness_level = level;
+                        save(settings.persist, "brightness", |saved| {
+                            saved.brightness = Some(level)
+                        });
+                    }
+                    Control::SetGamma(new_gamma) => {
+                        eprintln!("★ Received gamma from socket: {new_gamma}");
+                        gamma = new_gamma;
+                        save(settings.persist, "gamma", |saved| saved.gamma = Some(gamma));
                     }
                 }
-                // Restart loop immediately to apply the new temperature
+                // Restart loop immediately to fade to the new temperature;
+                // a fade already running continues from where it is
+                fade = Fade::start(shown, settings.fade);
+                continue;
+            }
+            Some((new_lat, new_lon)) = location_rx.recv() => {
+                eprintln!("Location updated: {new_lat:.4}, {new_lon:.4}");
+                location = Some((new_lat, new_lon));
+                location::remember((new_lat, new_lon));
                 continue;
             }
-            _ = tokio::time::sleep(tokio::time::Duration::from_millis(wait_ms as u64)) => {
+            _ = tokio::time::sleep(wait) => {
                 // Timeout, continue loop
             }
+            Ok(mut ready) = wayland_fd.readable() => {
+                // Events are read below and dispatched at the top of the loop
+                ready.clear_ready();
+            }
+        }
+
+        if reload {
+            match Settings::load(&opts) {
+                Ok(mut new) => {
+                    // The socket and D-Bus service keep running as started
+                    if new.socket != settings.socket {
+                        eprintln!("Changing socket requires a restart, keeping the current one");
+                        new.socket = settings.socket.clone();
+                    }
+                    if new.dbus != settings.dbus {
+                        eprintln!("Changing dbus requires a restart, keeping the current setting");
+                        new.dbus = settings.dbus;
+                    }
+                    if new.location != settings.location
+                        || new.manual != settings.manual
+                        || new.location_sources != settings.location_sources
+                        || new.location_threshold != settings.location_threshold
+                    {
+                        if let Some(tracker) = location_tracker.take() {
+                            tracker.abort();
+                        }
+                        while location_rx.try_recv().is_ok() {}
+                        location_tracker = location::spawn_tracker(&new, &location_tx);
+                        match location::resolve(&new, &mut location_rx, Duration::ZERO).await {
+                            Ok(Some(found)) => location = Some(found),
+                            // GeoClue reports to the loop; keep the current
+                            // coordinates until it does
+                            _ if location_tracker.is_some() && location.is_some() => {}
+                            Ok(None) => location = None,
+                            Err(e) => eprintln!("Keeping previous location: {e:#}"),
+                        }
+                    }
+                    state.reconfigure(&qh, new.filter.clone(), new.profiles.clone());
+                    // Keep a range set over IPC unless the file's range changed
+                    if (new.low_temp, new.high_temp) != (settings.low_temp, settings.high_temp) {
+                        (low_temp, high_temp) = (new.low_temp, new.high_temp);
+                        fade = Fade::start(shown, new.fade);
+                        save(new.persist, "temperature range", |saved| {
+                            saved.temperature = Some((low_temp, high_temp))
+                        });
+                    }
+                    if new.brightness != settings.brightness {
+                        brightness_level = new.brightness;
+                        fade = Fade::start(shown, new.fade);
+                        save(new.persist, "brightness", |saved| {
+                            saved.brightness = Some(brightness_level)
+                        });
+                    }
+                    if new.gamma != settings.gamma {
+                        gamma = new.gamma;
+                        save(new.persist, "gamma", |saved| saved.gamma = Some(gamma));
+                    }
+                    settings = new;
+                    eprintln!("Configuration reloaded");
+                }
+                Err(e) => eprintln!("Config reload failed, keeping previous settings: {e:#}"),
+            }
         }
 
         // Check for wayland events after potential signal/timeout
@@ -200,7 +436,7 @@ async fn main() -> Result<()> {
                     // no events, drop guard to cancel read
                 }
                 Ok(_) => {
-                    let conn_ready = fds[0].revents().map_or(false, |flags| {
+                    let conn_ready = fds[0].revents().is_some_and(|flags| {
                         flags
                             .intersects(PollFlags::POLLIN | PollFlags::POLLERR | PollFlags::POLLHUP)
                     });
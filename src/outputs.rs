@@ -0,0 +1,183 @@
+use anyhow::{Context, Result, anyhow};
+use glob::Pattern;
+
+use crate::color::{
+    Gamma, MAX_TEMP, MIN_TEMP, parse_gamma, validate_brightness, validate_temperature,
+    validate_temperature_range,
+};
+
+/// Selects outputs by `wl_output` name or description. An empty filter
+/// selects every output.
+#[derive(Clone, Debug, Default)]
+pub struct OutputFilter {
+    patterns: Vec<Pattern>,
+}
+
+impl OutputFilter {
+    pub fn new(selectors: &[String]) -> Result<Self> {
+        let patterns = selectors
+            .iter()
+            .map(|s| Pattern::new(s).with_context(|| format!("invalid output pattern {s:?}")))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { patterns })
+    }
+
+    pub fn matches(&self, name: Option<&str>, description: Option<&str>) -> bool {
+        if self.patterns.is_empty() {
+            return true;
+        }
+        self.patterns.iter().any(|p| {
+            name.is_some_and(|n| p.matches(n)) || description.is_some_and(|d| p.matches(d))
+        })
+    }
+}
+
+/// Per-output overrides applied on top of the global settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputProfile {
+    pub low_temp: Option<i32>,
+    pub high_temp: Option<i32>,
+    pub gamma: Option<Gamma>,
+    pub brightness: f64,
+    pub enabled: bool,
+}
+
+impl Default for OutputProfile {
+    fn default() -> Self {
+        Self {
+            low_temp: None,
+            high_temp: None,
+            gamma: None,
+            brightness: 1.0,
+            enabled: true,
+        }
+    }
+}
+
+impl OutputProfile {
+    /// Maps a temperature on the global `low..high` range onto this output's
+    /// own range, keeping the same relative position. Temperatures outside
+    /// the range, such as absolute keyframes, are extrapolated so they stay
+    /// warmer or cooler than the output's own ends.
+    pub fn kelvin_for(&self, kelvin: i32, low: i32, high: i32) -> i32 {
+        if self.low_temp.is_none() && self.high_temp.is_none() {
+            return kelvin;
+        }
+        let t = if high == low {
+            1.0
+        } else {
+            (kelvin - low) as f64 / (high - low) as f64
+        };
+        let lo = self.low_temp.unwrap_or(low);
+        let hi = self.high_temp.unwrap_or(high);
+        ((lo as f64 + (hi - lo) as f64 * t).round() as i32).clamp(MIN_TEMP, MAX_TEMP)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for k in [self.low_temp, self.high_temp].into_iter().flatten() {
+            validate_temperature(k)?;
+        }
+        if let (Some(lo), Some(hi)) = (self.low_temp, self.high_temp) {
+            validate_temperature_range(lo, hi)?;
+        }
+        if let Some(gamma) = self.gamma {
+            gamma.validate()?;
+        }
+        validate_brightness(self.brightness)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct ProfileRule {
+    pattern: Pattern,
+    profile: OutputProfile,
+}
+
+/// Ordered list of output profiles; the first matching pattern wins.
+#[derive(Clone, Debug, Default)]
+pub struct OutputProfiles {
+    rules: Vec<ProfileRule>,
+}
+
+impl OutputProfiles {
+    pub fn new(profiles: &[(String, OutputProfile)]) -> Result<Self> {
+        let rules = profiles
+            .iter()
+            .map(|(selector, profile)| {
+                profile
+                    .validate()
+                    .with_context(|| format!("invalid profile for output {selector:?}"))?;
+                let pattern = Pattern::new(selector)
+                    .with_context(|| format!("invalid output pattern {selector:?}"))?;
+                Ok(ProfileRule {
+                    pattern,
+                    profile: profile.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn resolve(&self, name: Option<&str>, description: Option<&str>) -> OutputProfile {
+        self.rules
+            .iter()
+            .find(|r| {
+                name.is_some_and(|n| r.pattern.matches(n))
+                    || description.is_some_and(|d| r.pattern.matches(d))
+            })
+            .map(|r| r.profile.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Parses `PATTERN:key=value,...`, e.g. `eDP-1:low=3500,gamma=1.1`. The
+/// pattern ends at the last `:` before the first `=`, so both the pattern and
+/// an `R:G:B` gamma may contain colons.
+pub fn parse_profile_spec(spec: &str) -> Result<(String, OutputProfile)> {
+    let head = spec.find('=').map_or(spec, |eq| &spec[..eq]);
+    let (selector, _) = head
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("expected PATTERN:key=value,..."))?;
+    let settings = &spec[selector.len() + 1..];
+    if selector.is_empty() {
+        return Err(anyhow!("missing output pattern"));
+    }
+    let mut profile = OutputProfile::default();
+    for setting in settings.split(',').filter(|s| !s.is_empty()) {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected key=value, got {setting:?}"))?;
+        let value = value.trim();
+        match key.trim() {
+            "low" => profile.low_temp = Some(value.parse()?),
+            "high" => profile.high_temp = Some(value.parse()?),
+            "gamma" => profile.gamma = Some(parse_gamma(value)?),
+            "brightness" => profile.brightness = value.parse()?,
+            "enabled" => profile.enabled = value.parse()?,
+            other => return Err(anyhow!("unknown profile key {other:?}")),
+        }
+    }
+    profile.validate()?;
+    Ok((selector.to_string(), profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kelvin_for_remaps_and_extrapolates() {
+        let profile = OutputProfile {
+            low_temp: Some(3500),
+            ..OutputProfile::default()
+        };
+        assert_eq!(profile.kelvin_for(4000, 4000, 6500), 3500);
+        assert_eq!(profile.kelvin_for(6500, 4000, 6500), 6500);
+        assert_eq!(profile.kelvin_for(5250, 4000, 6500), 5000);
+        // An absolute keyframe below the global range stays below the
+        // output's own low end
+        assert!(profile.kelvin_for(2700, 4000, 6500) < 3500);
+        assert_eq!(profile.kelvin_for(1000, 4000, 6500), MIN_TEMP);
+        assert_eq!(OutputProfile::default().kelvin_for(2700, 4000, 6500), 2700);
+    }
+}
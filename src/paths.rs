@@ -0,0 +1,25 @@
+use std::env;
+use std::path::PathBuf;
+
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback)),
+    }
+}
+
+pub fn config_file() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("redland").join("config.toml"))
+}
+
+pub fn state_file() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state").map(|dir| dir.join("redland").join("state.json"))
+}
+
+/// Default IPC socket location. There is no sensible fallback without
+/// `$XDG_RUNTIME_DIR`, since it must not be world-readable.
+pub fn socket_file() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| PathBuf::from(dir).join("redland.sock"))
+}
@@ -0,0 +1,56 @@
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::color::Gamma;
+use crate::paths;
+
+/// Runtime state kept across restarts in `$XDG_STATE_HOME/redland/state.json`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PersistedState {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<(i32, i32)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gamma: Option<Gamma>,
+    /// Last location reported by GeoClue, as `(lat, lon)`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<(f64, f64)>,
+}
+
+impl PersistedState {
+    /// Loads the saved state. A missing or unreadable file yields the empty
+    /// state, since nothing here is essential.
+    pub fn load() -> Self {
+        let Some(path) = paths::state_file() else {
+            return Self::default();
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Self::default();
+        };
+        serde_json::from_str(&text).unwrap_or_else(|e| {
+            eprintln!("Ignoring invalid state file {}: {e}", path.display());
+            Self::default()
+        })
+    }
+
+    /// Writes the state atomically so a crash never leaves a truncated file.
+    pub fn save(&self) -> Result<()> {
+        let path = paths::state_file().ok_or_else(|| anyhow!("no state directory"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("rename to {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn update(f: impl FnOnce(&mut Self)) -> Result<()> {
+        let mut state = Self::load();
+        f(&mut state);
+        state.save()
+    }
+}
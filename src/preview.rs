@@ -0,0 +1,88 @@
+use anyhow::Result;
+use chrono::{Datelike, Local};
+use std::time::Duration;
+
+use crate::cli::{Opts, ScheduleArgs};
+use crate::config::Settings;
+use crate::keyframes::{self, Level};
+use crate::location;
+use crate::scheduling::{SunSchedule, day_stops_on, format_hhmm};
+
+/// Runs `redland schedule`: prints the rule, sun times and keyframes that
+/// apply on a date, without contacting the daemon.
+pub async fn run(opts: &Opts, args: &ScheduleArgs) -> Result<()> {
+    let settings = Settings::load(opts)?;
+    // Only sources that answer immediately; GeoClue is not waited for
+    let (_tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let location = location::resolve(&settings, &mut rx, Duration::ZERO).await?;
+    let sched = SunSchedule::new(&settings, location);
+
+    let date = args.date.unwrap_or_else(|| Local::now().date_naive());
+    let stops = day_stops_on(date, &sched)?;
+    println!("Date:        {} ({})", date, date.weekday());
+    let rule = settings.schedule.rule_on(date);
+    match rule {
+        Some(rule) => println!(
+            "Rule:        {}",
+            rule.name.as_deref().unwrap_or("(unnamed)")
+        ),
+        None => println!("Rule:        none"),
+    }
+    if let Some((lat, lon)) = location {
+        println!("Location:    {:.4}, {:.4}", lat, lon);
+    }
+    if let Some(polar) = stops.polar {
+        println!("Polar:       {}", polar.name().replace('_', " "));
+    }
+    if let Some((sunrise, sunset)) = stops.sun_times {
+        println!(
+            "Sun times:   {} - {}",
+            format_hhmm(sunrise),
+            format_hhmm(sunset)
+        );
+    }
+    if sched.follows_elevation(date, stops) {
+        println!(
+            "Temperature follows the sun's elevation: {}K below {}°, {}K above {}°",
+            settings.low_temp, settings.night_elevation, settings.high_temp, settings.day_elevation
+        );
+        return Ok(());
+    }
+
+    let keyframes = settings.schedule.keyframes_on(date);
+    let mut points: Vec<_> = keyframes::resolve(keyframes, date, stops)?
+        .into_iter()
+        .zip(keyframes)
+        .collect();
+    points.sort_by_key(|(point, _)| point.at);
+    println!(
+        "Keyframes{}:",
+        if settings.schedule.is_default_on(date) {
+            " (default)"
+        } else {
+            ""
+        }
+    );
+    for (point, keyframe) in points {
+        let kelvin = point.temp.kelvin(settings.low_temp, settings.high_temp);
+        let temp = match point.temp {
+            Level::Low => format!("low ({kelvin}K)"),
+            Level::High => format!("high ({kelvin}K)"),
+            Level::Kelvin(_) => format!("{kelvin}K"),
+        };
+        print!(
+            "  {}  {:<16} {}",
+            format_hhmm(point.at),
+            keyframe.at.to_string(),
+            temp
+        );
+        if point.brightness < 1.0 {
+            print!(", brightness {:.2}", point.brightness);
+        }
+        if let Some(ease) = point.ease {
+            print!(", ease {}", ease.name());
+        }
+        println!();
+    }
+    Ok(())
+}
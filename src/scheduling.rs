@@ -1,8 +1,15 @@
 use anyhow::{Result, anyhow};
-use chrono::Duration;
-use sunrise::{Coordinates, SolarDay, SolarEvent};
+use chrono::{Days, Local, LocalResult, NaiveDate, NaiveTime, TimeZone};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use sunrise::{Coordinates, DawnType, SolarDay, SolarEvent};
 
 use crate::cli::ModeArg;
+use crate::config::Settings;
+use crate::keyframes::{self, Point, Schedule};
+use crate::location::FIXED_TIMES;
+use crate::solar::{self, Polar, SunPosition};
 
 #[derive(Copy, Clone, Debug)]
 pub struct DayStops {
@@ -10,6 +17,11 @@ pub struct DayStops {
     pub sunrise: i64,
     pub sunset: i64,
     pub night: i64,
+    /// Set when the sun neither rises nor sets on this day.
+    pub polar: Option<Polar>,
+    /// Sunrise and sunset to report, which the elevation curve's stops are
+    /// not. `None` on polar days.
+    pub sun_times: Option<(i64, i64)>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -20,11 +32,65 @@ pub enum DayPhase {
     Sunset,
 }
 
+impl DayPhase {
+    /// Lowercase name used in status reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            DayPhase::Night => "night",
+            DayPhase::Sunrise => "sunrise",
+            DayPhase::Day => "day",
+            DayPhase::Sunset => "sunset",
+        }
+    }
+}
+
 pub struct TrayOverride {
     pub mode: ModeArg,
     pub expires_at: i64,
 }
 
+/// How often the temperature is updated while a fade runs.
+pub const FADE_FRAME: Duration = Duration::from_millis(50);
+
+/// An animated change away from the temperature and brightness on screen
+/// when a mode, range or override changed. The end point is recomputed each
+/// frame, so the fade lands wherever the schedule is by then.
+#[derive(Copy, Clone, Debug)]
+pub struct Fade {
+    from_temp: i32,
+    from_brightness: f64,
+    started: Instant,
+    duration: Duration,
+}
+
+impl Fade {
+    /// A fade from `shown` lasting `duration`, or `None` if there is nothing
+    /// on screen yet or fades are disabled.
+    pub fn start(shown: Option<(i32, f64)>, duration: Duration) -> Option<Self> {
+        let (from_temp, from_brightness) = shown?;
+        (!duration.is_zero()).then(|| Fade {
+            from_temp,
+            from_brightness,
+            started: Instant::now(),
+            duration,
+        })
+    }
+
+    /// Temperature and brightness to show now on the way to `temp` and
+    /// `brightness`, or `None` once the fade is over.
+    pub fn frame(&self, temp: i32, brightness: f64) -> Option<(i32, f64)> {
+        let t = self.started.elapsed().as_secs_f64() / self.duration.as_secs_f64();
+        if t >= 1.0 {
+            return None;
+        }
+        let easing = Easing::Smoothstep;
+        Some((
+            easing.kelvin(t, self.from_temp, temp).round() as i32,
+            self.from_brightness + (brightness - self.from_brightness) * easing.shape(t),
+        ))
+    }
+}
+
 pub fn parse_hhmm(s: &str) -> Result<i64> {
     let parts: Vec<_> = s.split(':').collect();
     if parts.len() != 2 {
@@ -32,99 +98,922 @@ pub fn parse_hhmm(s: &str) -> Result<i64> {
     }
     let h: i64 = parts[0].parse()?;
     let m: i64 = parts[1].parse()?;
+    if !(0..24).contains(&h) || !(0..60).contains(&m) {
+        return Err(anyhow!("time out of range"));
+    }
     Ok(h * 3600 + m * 60)
 }
 
-pub fn compute_day_stops(
-    now: i64,
-    lat: f64,
-    lon: f64,
-    duration: i64,
-    manual: Option<(i64, i64)>,
-) -> Result<DayStops> {
-    let midnight = now - (now % 86400);
-    if let Some((sunrise_s, sunset_s)) = manual {
-        let dawn = sunrise_s - duration;
-        let night = sunset_s + duration;
-        return Ok(DayStops {
-            dawn: midnight + dawn,
-            sunrise: midnight + sunrise_s,
-            sunset: midnight + sunset_s,
-            night: midnight + night,
-        });
+/// Formats a timestamp as local `HH:MM`.
+pub fn format_hhmm(ts: i64) -> String {
+    Local
+        .timestamp_opt(ts, 0)
+        .single()
+        .map(|t| t.format("%H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// Local calendar date of a timestamp.
+fn local_date(ts: i64) -> Result<NaiveDate> {
+    Local
+        .timestamp_opt(ts, 0)
+        .single()
+        .map(|t| t.date_naive())
+        .ok_or_else(|| anyhow!("invalid timestamp"))
+}
+
+/// Timestamp of the local wall-clock time `seconds` after midnight on
+/// `date`. A time repeated when clocks go back resolves to its first
+/// occurrence; a time skipped when they go forward keeps the offset from
+/// before the jump, landing as far past the jump as it was into the gap.
+pub fn local_timestamp(date: NaiveDate, seconds: i64) -> Result<i64> {
+    let time = NaiveTime::from_num_seconds_from_midnight_opt(seconds as u32, 0)
+        .ok_or_else(|| anyhow!("time of day out of range"))?;
+    let naive = date.and_time(time);
+    match Local.from_local_datetime(&naive) {
+        LocalResult::Single(t) => Ok(t.timestamp()),
+        // Not necessarily in chronological order
+        LocalResult::Ambiguous(a, b) => Ok(a.timestamp().min(b.timestamp())),
+        LocalResult::None => {
+            let day_before = naive - Days::new(1);
+            let offset = Local
+                .offset_from_local_datetime(&day_before)
+                .earliest()
+                .ok_or_else(|| anyhow!("no UTC offset for {naive}"))?;
+            Ok(naive.and_utc().timestamp() - i64::from(offset.local_minus_utc()))
+        }
+    }
+}
+
+/// Start of the next local day after `now`.
+pub fn next_local_midnight(now: i64) -> Result<i64> {
+    let tomorrow = local_date(now)?
+        .succ_opt()
+        .ok_or_else(|| anyhow!("date out of range"))?;
+    local_timestamp(tomorrow, 0)
+}
+
+/// Longest wait between updates while the temperature follows the sun's
+/// elevation.
+const ELEVATION_UPDATE_INTERVAL: i64 = 60;
+/// Smallest temperature change worth a new gamma table, in mireds. Mireds
+/// rather than kelvin, as a kelvin step is far more visible when warm.
+const MIRED_STEP: f64 = 0.5;
+/// Smallest brightness change worth a new gamma table.
+const BRIGHTNESS_STEP: f64 = 1.0 / 256.0;
+/// Sun elevation (degrees) at and above which it is full day.
+pub const DEFAULT_DAY_ELEVATION: f64 = 3.0;
+/// Sun elevation (degrees) at and below which it is full night.
+pub const DEFAULT_NIGHT_ELEVATION: f64 = -6.0;
+
+/// How the temperature moves between night and day.
+#[derive(Copy, Clone, Debug, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Curve {
+    /// Linearly in time over `duration` around sunrise and sunset
+    Time,
+    /// With the sun's elevation between the night and day thresholds
+    Elevation,
+}
+
+/// Shape of a transition between two keyframes.
+#[derive(Copy, Clone, Debug, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Easing {
+    /// Constant rate in kelvin
+    Linear,
+    /// Slow start and end, `3t² - 2t³`
+    Smoothstep,
+    /// Slow start and end along half a cosine wave
+    Cosine,
+    /// Constant ratio between steps, so warm temperatures change slowly
+    Exponential,
+    /// Constant rate in mireds (1e6 / kelvin), perceptually even
+    Mired,
+}
+
+impl Easing {
+    /// Lowercase name, as accepted by `--easing`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::Smoothstep => "smoothstep",
+            Easing::Cosine => "cosine",
+            Easing::Exponential => "exponential",
+            Easing::Mired => "mired",
+        }
+    }
+
+    /// Reshapes linear progress `t` for the time-based easings.
+    fn shape(self, t: f64) -> f64 {
+        match self {
+            Easing::Smoothstep => t * t * (3.0 - 2.0 * t),
+            Easing::Cosine => (1.0 - (std::f64::consts::PI * t).cos()) / 2.0,
+            Easing::Linear | Easing::Exponential | Easing::Mired => t,
+        }
+    }
+
+    /// Temperature at progress `t` from `a` to `b` kelvin.
+    fn kelvin(self, t: f64, a: i32, b: i32) -> f64 {
+        let (a, b) = (a as f64, b as f64);
+        match self {
+            Easing::Exponential => a * (b / a).powf(t),
+            Easing::Mired => 1.0 / (1.0 / a + (1.0 / b - 1.0 / a) * t),
+            _ => a + (b - a) * self.shape(t),
+        }
+    }
+}
+
+/// What to do on days when the sun neither rises nor sets.
+#[derive(Copy, Clone, Debug, ValueEnum, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PolarPolicy {
+    /// Keep the day temperature
+    Day,
+    /// Keep the night temperature
+    Night,
+    /// Follow the sun's elevation through twilight
+    Elevation,
+    /// Use fixed sunrise and sunset times
+    Fixed,
+}
+
+/// Where the morning fade starts or the evening fade ends.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Twilight {
+    /// `duration` before sunrise or after sunset
+    Duration,
+    Civil,
+    Nautical,
+    Astronomical,
+    /// When the sun is at this elevation (degrees, below the horizon)
+    Elevation(f64),
+}
+
+impl Twilight {
+    /// The sun's elevation at this anchor, or `None` for `Duration`.
+    fn elevation(self) -> Option<f64> {
+        match self {
+            Twilight::Duration => None,
+            Twilight::Civil => Some(-6.0),
+            Twilight::Nautical => Some(-12.0),
+            Twilight::Astronomical => Some(-18.0),
+            Twilight::Elevation(e) => Some(e),
+        }
+    }
+
+    fn event(self, morning: bool) -> Option<SolarEvent> {
+        let dawn_type = match self {
+            Twilight::Duration => return None,
+            Twilight::Civil => DawnType::Civil,
+            Twilight::Nautical => DawnType::Nautical,
+            Twilight::Astronomical => DawnType::Astronomical,
+            Twilight::Elevation(e) => {
+                return Some(SolarEvent::Elevation {
+                    // Measured downwards from the horizon
+                    elevation: (-e).to_radians(),
+                    morning,
+                });
+            }
+        };
+        Some(if morning {
+            SolarEvent::Dawn(dawn_type)
+        } else {
+            SolarEvent::Dusk(dawn_type)
+        })
+    }
+}
+
+/// Parses `duration`, `civil`, `nautical`, `astronomical` or an elevation in
+/// degrees below the horizon such as `-4.5`.
+pub fn parse_twilight(s: &str) -> Result<Twilight> {
+    Ok(match s {
+        "duration" => Twilight::Duration,
+        "civil" => Twilight::Civil,
+        "nautical" => Twilight::Nautical,
+        "astronomical" => Twilight::Astronomical,
+        _ => {
+            let elevation: f64 = s.parse().map_err(|_| {
+                anyhow!("expected duration, civil, nautical, astronomical or an elevation")
+            })?;
+            if !(-90.0..solar::HORIZON).contains(&elevation) {
+                return Err(anyhow!(
+                    "elevation must be below the horizon ({} degrees)",
+                    solar::HORIZON
+                ));
+            }
+            Twilight::Elevation(elevation)
+        }
+    })
+}
+
+/// Everything the daily schedule depends on besides the date.
+#[derive(Copy, Clone, Debug)]
+pub struct SunSchedule<'a> {
+    pub lat: f64,
+    pub lon: f64,
+    pub duration: i64,
+    pub manual: Option<(i64, i64)>,
+    pub polar: PolarPolicy,
+    pub curve: Curve,
+    pub day_elevation: f64,
+    pub night_elevation: f64,
+    pub dawn: Twilight,
+    pub dusk: Twilight,
+    /// Easing of transitions whose keyframe does not set its own
+    pub easing: Easing,
+    pub schedule: &'a Schedule,
+}
+
+impl<'a> SunSchedule<'a> {
+    /// The schedule for `settings` at `location`, or at fixed times without
+    /// one.
+    pub fn new(settings: &'a Settings, location: Option<(f64, f64)>) -> Self {
+        let (lat, lon) = location.unwrap_or_default();
+        Self {
+            lat,
+            lon,
+            duration: settings.duration,
+            manual: settings
+                .manual
+                .or(location.is_none().then_some(FIXED_TIMES)),
+            polar: settings.polar,
+            curve: settings.curve,
+            day_elevation: settings.day_elevation,
+            night_elevation: settings.night_elevation,
+            dawn: settings.dawn,
+            dusk: settings.dusk,
+            easing: settings.easing,
+            schedule: &settings.schedule,
+        }
+    }
+
+    /// Whether the elevation curve shapes the default schedule on `date`.
+    fn elevation_curve(&self, date: NaiveDate) -> bool {
+        self.curve == Curve::Elevation && self.schedule.is_default_on(date)
+    }
+
+    /// Whether the temperature currently follows the sun's elevation rather
+    /// than the keyframes.
+    pub fn follows_elevation(&self, date: NaiveDate, stops: DayStops) -> bool {
+        if !self.schedule.is_default_on(date) {
+            false
+        } else if stops.polar.is_some() {
+            self.polar == PolarPolicy::Elevation
+        } else {
+            self.elevation_curve(date) && self.manual.is_none()
+        }
     }
-    let coords = Coordinates::new(lat, lon).ok_or_else(|| anyhow!("invalid coordinates"))?;
-    let date = chrono::DateTime::from_timestamp(now, 0)
-        .ok_or_else(|| anyhow!("invalid timestamp"))?
-        .date_naive();
+}
+
+/// Stops for the local day containing `now`.
+pub fn compute_day_stops(now: i64, sched: &SunSchedule) -> Result<DayStops> {
+    day_stops_on(local_date(now)?, sched)
+}
+
+/// Stops for the local day after the one containing `now`.
+fn next_day_stops(now: i64, sched: &SunSchedule) -> Result<DayStops> {
+    let tomorrow = local_date(now)?
+        .succ_opt()
+        .ok_or_else(|| anyhow!("date out of range"))?;
+    day_stops_on(tomorrow, sched)
+}
+
+pub fn day_stops_on(date: NaiveDate, sched: &SunSchedule) -> Result<DayStops> {
+    let stops = |sunrise: i64, sunset: i64, polar: Option<Polar>| DayStops {
+        dawn: sunrise - sched.duration,
+        sunrise,
+        sunset,
+        night: sunset + sched.duration,
+        polar,
+        sun_times: polar.is_none().then_some((sunrise, sunset)),
+    };
+    if let Some((sunrise_s, sunset_s)) = sched.manual {
+        return Ok(stops(
+            local_timestamp(date, sunrise_s)?,
+            local_timestamp(date, sunset_s)?,
+            None,
+        ));
+    }
+
+    let noon = local_timestamp(date, 12 * 3600)?;
+    let coords =
+        Coordinates::new(sched.lat, sched.lon).ok_or_else(|| anyhow!("invalid coordinates"))?;
     let solar_day = SolarDay::new(coords, date);
-    let sunrise_ts = solar_day
-        .event_time(SolarEvent::Sunrise)
-        .timestamp();
-    let sunset_ts = solar_day
-        .event_time(SolarEvent::Sunset)
-        .timestamp();
-    Ok(DayStops {
-        dawn: sunrise_ts - duration,
-        sunrise: sunrise_ts,
-        sunset: sunset_ts,
-        night: sunset_ts + duration,
+    let sunrise = solar_day.event_time(SolarEvent::Sunrise).timestamp();
+    let sunset = solar_day.event_time(SolarEvent::Sunset).timestamp();
+    let polar = solar::polar_state(noon, sched.lat, sched.lon).or_else(|| {
+        // Right at the edge of the polar season the event calculation can
+        // fail even though the sun still touches the horizon
+        (sunrise >= sunset).then(|| {
+            if solar::position(noon, sched.lat, sched.lon).elevation > solar::HORIZON {
+                Polar::Day
+            } else {
+                Polar::Night
+            }
+        })
+    });
+    let Some(polar) = polar else {
+        if sched.elevation_curve(date) {
+            let (lowest, highest) = solar::elevation_range(noon, sched.lat, sched.lon);
+            // Otherwise the curve never reaches one of its ends and there
+            // are no crossings to report; keep the sunrise and sunset stops
+            if lowest < sched.night_elevation && highest > sched.day_elevation {
+                let crossing = |elevation: f64, morning| {
+                    solar_day
+                        .event_time(SolarEvent::Elevation {
+                            // Measured downwards from the horizon
+                            elevation: (-elevation).to_radians(),
+                            morning,
+                        })
+                        .timestamp()
+                };
+                return Ok(DayStops {
+                    dawn: crossing(sched.night_elevation, true),
+                    sunrise: crossing(sched.day_elevation, true),
+                    sunset: crossing(sched.day_elevation, false),
+                    night: crossing(sched.night_elevation, false),
+                    polar: None,
+                    sun_times: Some((sunrise, sunset)),
+                });
+            }
+        }
+        let (lowest, _) = solar::elevation_range(noon, sched.lat, sched.lon);
+        // An anchor the sun does not sink to today falls back to `duration`
+        let anchor = |twilight: Twilight, morning| {
+            let elevation = twilight.elevation()?;
+            let event = twilight.event(morning)?;
+            (lowest < elevation).then(|| solar_day.event_time(event).timestamp())
+        };
+        let mut day = stops(sunrise, sunset, None);
+        if let Some(dawn) = anchor(sched.dawn, true) {
+            day.dawn = dawn;
+        }
+        if let Some(night) = anchor(sched.dusk, false) {
+            day.night = night;
+        }
+        return Ok(day);
+    };
+
+    let day_start = local_timestamp(date, 0)?;
+    let day_end = local_timestamp(
+        date.succ_opt()
+            .ok_or_else(|| anyhow!("date out of range"))?,
+        0,
+    )?;
+    let keep = |polar_day: bool| {
+        // Zero-length transitions around a plateau covering the whole day
+        let (rise, set) = if polar_day {
+            (day_start, day_end)
+        } else {
+            (day_end, day_end)
+        };
+        DayStops {
+            dawn: rise,
+            sunrise: rise,
+            sunset: set,
+            night: set,
+            polar: Some(polar),
+            sun_times: None,
+        }
+    };
+    Ok(match sched.polar {
+        PolarPolicy::Day => keep(true),
+        PolarPolicy::Night => keep(false),
+        PolarPolicy::Elevation => keep(polar == Polar::Day),
+        PolarPolicy::Fixed => {
+            let (sunrise_s, sunset_s) = FIXED_TIMES;
+            stops(
+                local_timestamp(date, sunrise_s)?,
+                local_timestamp(date, sunset_s)?,
+                Some(polar),
+            )
+        }
     })
 }
 
-pub fn next_sunrise_timestamp(
-    now: i64,
-    current: DayStops,
-    lat: f64,
-    lon: f64,
-    duration: i64,
-    manual: Option<(i64, i64)>,
-) -> Result<i64> {
+/// When a manual override should end: the next sunrise, or the next local
+/// midnight on days without one.
+pub fn next_sunrise_timestamp(now: i64, current: DayStops, sched: &SunSchedule) -> Result<i64> {
     if now < current.sunrise {
         return Ok(current.sunrise);
     }
-    // Calculate next day's stops properly using chrono to handle DST
-    let current_dt = chrono::DateTime::from_timestamp(now, 0)
-        .ok_or_else(|| anyhow!("invalid timestamp"))?;
-    let tomorrow_dt = current_dt + Duration::days(1);
-    let tomorrow = tomorrow_dt.timestamp();
-    let next = compute_day_stops(tomorrow, lat, lon, duration, manual)?;
-    Ok(next.sunrise)
+    Ok(next_day_stops(now, sched)?.sunrise)
 }
 
-pub fn interpolate(now: i64, start: i64, stop: i64, a: i32, b: i32) -> i32 {
-    if start == stop {
-        return b;
-    }
-    let t = ((now - start) as f64 / (stop - start) as f64).clamp(0.0, 1.0);
-    let v = a as f64 + (b - a) as f64 * t;
-    v.round() as i32
-}
-
-pub fn temperature_for(now: i64, stops: DayStops, low: i32, high: i32) -> i32 {
-    if now < stops.dawn {
-        low
-    } else if now < stops.sunrise {
-        interpolate(now, stops.dawn, stops.sunrise, low, high)
-    } else if now < stops.sunset {
-        high
-    } else if now < stops.night {
-        interpolate(now, stops.sunset, stops.night, high, low)
+/// Timestamp of the next keyframe after `now`, or `None` while a polar day
+/// or night is not following fixed times.
+pub fn next_transition_timestamp(
+    now: i64,
+    current: DayStops,
+    sched: &SunSchedule,
+) -> Result<Option<i64>> {
+    if current.polar.is_some() && sched.polar != PolarPolicy::Fixed {
+        return Ok(None);
+    }
+    let points = timeline(now, current, sched)?;
+    Ok(points.iter().map(|p| p.at).find(|&at| now < at))
+}
+
+/// When the temperature should next be recomputed: once it has moved by
+/// about [`MIRED_STEP`] or the brightness by [`BRIGHTNESS_STEP`] at the
+/// current rate, otherwise at the next keyframe or local midnight.
+pub fn next_update(
+    now: f64,
+    stops: DayStops,
+    sched: &SunSchedule,
+    low: i32,
+    high: i32,
+) -> Result<f64> {
+    let second = now.floor() as i64;
+    let midnight = next_local_midnight(second)? as f64;
+    let horizon = if sched.follows_elevation(local_date(second)?, stops) {
+        now + ELEVATION_UPDATE_INTERVAL as f64
     } else {
-        low
+        let points = timeline(second, stops, sched)?;
+        let (_, next) = segment(now, &points);
+        if next.at as f64 > now {
+            next.at as f64
+        } else {
+            midnight
+        }
+    }
+    .min(midnight);
+
+    let (k0, b0, _) = sample(now, stops, sched, low, high)?;
+    let (k1, b1, _) = sample(now + 1.0, stops, sched, low, high)?;
+    let steps_per_second =
+        ((1e6 / k1 - 1e6 / k0).abs() / MIRED_STEP).max((b1 - b0).abs() / BRIGHTNESS_STEP);
+    if steps_per_second == 0.0 {
+        return Ok(horizon);
     }
+    let interval = (1.0 / steps_per_second).max(FADE_FRAME.as_secs_f64());
+    Ok((now + interval).min(horizon))
 }
 
-pub fn phase_for(now: i64, stops: DayStops) -> DayPhase {
-    if now < stops.dawn {
-        DayPhase::Night
-    } else if now < stops.sunrise {
+/// What the schedule calls for at a moment.
+#[derive(Copy, Clone, Debug)]
+pub struct Target {
+    pub temp: i32,
+    pub brightness: f64,
+    pub phase: DayPhase,
+}
+
+/// Temperature, brightness and phase the schedule calls for at `now`.
+pub fn evaluate(
+    now: f64,
+    stops: DayStops,
+    sched: &SunSchedule,
+    low: i32,
+    high: i32,
+) -> Result<Target> {
+    let (temp, brightness, phase) = sample(now, stops, sched, low, high)?;
+    Ok(Target {
+        temp: temp.round() as i32,
+        brightness,
+        phase,
+    })
+}
+
+/// Unrounded temperature and brightness at `now`, and the phase.
+fn sample(
+    now: f64,
+    stops: DayStops,
+    sched: &SunSchedule,
+    low: i32,
+    high: i32,
+) -> Result<(f64, f64, DayPhase)> {
+    let second = now.floor() as i64;
+    if sched.follows_elevation(local_date(second)?, stops) {
+        let sun = solar::position(second, sched.lat, sched.lon);
+        return Ok((
+            temperature_for_elevation(sun.elevation, sched, low, high),
+            1.0,
+            phase_for_elevation(sun, sched),
+        ));
+    }
+    let points = timeline(second, stops, sched)?;
+    let (prev, next) = segment(now, &points);
+    let (from, to) = (prev.temp.kelvin(low, high), next.temp.kelvin(low, high));
+    // A keyframe's easing shapes the transition that starts at it
+    let easing = prev.ease.unwrap_or(sched.easing);
+    let temp = interpolate(now, prev.at, next.at, from, to, easing);
+    let t = easing.shape(progress(now, prev.at, next.at));
+    let phase = if to > from {
         DayPhase::Sunrise
-    } else if now < stops.sunset {
-        DayPhase::Day
-    } else if now < stops.night {
+    } else if to < from {
         DayPhase::Sunset
+    } else if 2.0 * temp >= (low + high) as f64 {
+        DayPhase::Day
     } else {
         DayPhase::Night
+    };
+    Ok((
+        temp,
+        prev.brightness + (next.brightness - prev.brightness) * t,
+        phase,
+    ))
+}
+
+/// Keyframes placed on the days before, of and after `now`, in time order,
+/// so segments crossing midnight interpolate across it.
+fn timeline(now: i64, today: DayStops, sched: &SunSchedule) -> Result<Vec<Point>> {
+    let date = local_date(now)?;
+    let mut points = Vec::new();
+    // In day order, so where days meet at the same instant (such as polar
+    // plateaus at midnight) the later day's keyframes come last and win
+    for day in [date.pred_opt(), Some(date), date.succ_opt()]
+        .into_iter()
+        .flatten()
+    {
+        let stops = if day == date {
+            today
+        } else {
+            day_stops_on(day, sched)?
+        };
+        points.extend(keyframes::resolve(
+            sched.schedule.keyframes_on(day),
+            day,
+            stops,
+        )?);
+    }
+    // Stable, so keyframes at the same time keep their configured order
+    points.sort_by_key(|p| p.at);
+    Ok(points)
+}
+
+/// The last keyframe at or before `now` and the first one after it. Before
+/// the first or after the last keyframe both are the same.
+fn segment(now: f64, points: &[Point]) -> (Point, Point) {
+    let i = points.partition_point(|p| p.at as f64 <= now);
+    let prev = points[i.saturating_sub(1)];
+    let next = points[i.min(points.len() - 1)];
+    (prev, next)
+}
+
+fn temperature_for_elevation(elevation: f64, sched: &SunSchedule, low: i32, high: i32) -> f64 {
+    let t = ((elevation - sched.night_elevation) / (sched.day_elevation - sched.night_elevation))
+        .clamp(0.0, 1.0);
+    low as f64 + (high - low) as f64 * t
+}
+
+fn phase_for_elevation(sun: SunPosition, sched: &SunSchedule) -> DayPhase {
+    if sun.elevation >= sched.day_elevation {
+        DayPhase::Day
+    } else if sun.elevation <= sched.night_elevation {
+        DayPhase::Night
+    } else if sun.hour_angle < 0.0 {
+        DayPhase::Sunrise
+    } else {
+        DayPhase::Sunset
+    }
+}
+
+/// How far `now` has moved from `start` to `stop`, within `0..=1`.
+fn progress(now: f64, start: i64, stop: i64) -> f64 {
+    if start == stop {
+        return 1.0;
+    }
+    ((now - start as f64) / (stop - start) as f64).clamp(0.0, 1.0)
+}
+
+/// Unrounded temperature at `now` on the way from `a` to `b`.
+pub fn interpolate(now: f64, start: i64, stop: i64, a: i32, b: i32, easing: Easing) -> f64 {
+    easing.kelvin(progress(now, start, stop), a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+    use std::sync::Mutex;
+
+    /// Held while a test depends on the local timezone.
+    static ZONE: Mutex<()> = Mutex::new(());
+
+    /// Runs `f` with `TZ` set to `zone`, on a fresh thread since chrono
+    /// caches the local timezone per thread.
+    fn in_zone<T: Send>(zone: &str, f: impl FnOnce() -> T + Send) -> T {
+        let _guard = ZONE.lock().unwrap_or_else(|e| e.into_inner());
+        // SAFETY: every test reading the local timezone holds `ZONE`
+        unsafe { std::env::set_var("TZ", zone) };
+        std::thread::scope(|s| {
+            s.spawn(f)
+                .join()
+                .unwrap_or_else(|e| std::panic::resume_unwind(e))
+        })
+    }
+
+    fn settings(args: &[&str]) -> Settings {
+        Settings::parse(args, "").unwrap()
+    }
+
+    fn at(date: NaiveDate, hh: i64, mm: i64) -> i64 {
+        local_timestamp(date, hh * 3600 + mm * 60).unwrap()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn utc(y: i32, m: u32, d: u32, hh: u32, mm: u32) -> i64 {
+        date(y, m, d)
+            .and_hms_opt(hh, mm, 0)
+            .unwrap()
+            .and_utc()
+            .timestamp()
+    }
+
+    #[test]
+    fn local_timestamp_single_gap_and_overlap() {
+        let cases = [
+            // (zone, local date, local HH:MM, expected UTC)
+            (
+                "Europe/Ljubljana",
+                date(2026, 1, 15),
+                (12, 0),
+                utc(2026, 1, 15, 11, 0),
+            ),
+            // Skipped 02:00-03:00 keeps the CET offset
+            (
+                "Europe/Ljubljana",
+                date(2026, 3, 29),
+                (2, 30),
+                utc(2026, 3, 29, 1, 30),
+            ),
+            // Repeated 02:00-03:00 resolves to the first, CEST, occurrence
+            (
+                "Europe/Ljubljana",
+                date(2026, 10, 25),
+                (2, 30),
+                utc(2026, 10, 25, 0, 30),
+            ),
+            (
+                "America/New_York",
+                date(2026, 3, 8),
+                (2, 30),
+                utc(2026, 3, 8, 7, 30),
+            ),
+            (
+                "America/New_York",
+                date(2026, 11, 1),
+                (1, 30),
+                utc(2026, 11, 1, 5, 30),
+            ),
+            // Half-hour shifts: 02:00-02:30 skipped, 01:30-02:00 repeated
+            (
+                "Australia/Lord_Howe",
+                date(2026, 10, 4),
+                (2, 15),
+                utc(2026, 10, 3, 15, 45),
+            ),
+            (
+                "Australia/Lord_Howe",
+                date(2026, 4, 5),
+                (1, 45),
+                utc(2026, 4, 4, 14, 45),
+            ),
+        ];
+        for (zone, day, (hh, mm), expected) in cases {
+            in_zone(zone, || {
+                assert_eq!(at(day, hh, mm), expected, "{zone} {day}")
+            });
+        }
+    }
+
+    #[test]
+    fn next_local_midnight_follows_day_length() {
+        let cases = [
+            ("Europe/Ljubljana", date(2026, 3, 29), 23 * 3600),
+            ("Europe/Ljubljana", date(2026, 10, 25), 25 * 3600),
+            ("America/New_York", date(2026, 3, 8), 23 * 3600),
+            ("America/New_York", date(2026, 11, 1), 25 * 3600),
+            ("Australia/Lord_Howe", date(2026, 10, 4), 23 * 3600 + 1800),
+            ("Australia/Lord_Howe", date(2026, 4, 5), 24 * 3600 + 1800),
+            ("Australia/Lord_Howe", date(2026, 6, 1), 24 * 3600),
+        ];
+        for (zone, day, length) in cases {
+            in_zone(zone, || {
+                let start = at(day, 0, 0);
+                for now in [start, at(day, 1, 45), at(day, 12, 0), at(day, 23, 59)] {
+                    assert_eq!(
+                        next_local_midnight(now).unwrap(),
+                        start + length,
+                        "{zone} {day}"
+                    );
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn manual_times_are_local_wall_clock_across_dst() {
+        let settings = settings(&["--sunrise", "06:30", "--sunset", "18:00"]);
+        let sched = SunSchedule::new(&settings, None);
+        for zone in [
+            "Europe/Ljubljana",
+            "America/New_York",
+            "Australia/Lord_Howe",
+        ] {
+            in_zone(zone, || {
+                let mut day = date(2026, 1, 1);
+                while day.year() == 2026 {
+                    let stops = day_stops_on(day, &sched).unwrap();
+                    assert_eq!(format_hhmm(stops.sunrise), "06:30", "{zone} {day}");
+                    assert_eq!(format_hhmm(stops.sunset), "18:00", "{zone} {day}");
+                    assert_eq!(stops.dawn, stops.sunrise - settings.duration);
+                    assert_eq!(stops.night, stops.sunset + settings.duration);
+                    day = day.succ_opt().unwrap();
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn manual_time_in_the_gap_lands_after_the_jump() {
+        let settings = settings(&["--sunrise", "02:30", "--sunset", "18:00"]);
+        let sched = SunSchedule::new(&settings, None);
+        in_zone("Europe/Ljubljana", || {
+            let stops = day_stops_on(date(2026, 3, 29), &sched).unwrap();
+            assert_eq!(format_hhmm(stops.sunrise), "03:30");
+        });
+    }
+
+    #[test]
+    fn solar_stops_fall_on_their_local_day() {
+        let places = [
+            ("Europe/Ljubljana", (46.05, 14.51)),
+            ("America/New_York", (40.71, -74.01)),
+            ("Australia/Lord_Howe", (-31.55, 159.08)),
+        ];
+        let settings = settings(&[]);
+        for (zone, location) in places {
+            let sched = SunSchedule::new(&settings, Some(location));
+            in_zone(zone, || {
+                let mut day = date(2026, 1, 1);
+                while day.year() == 2026 {
+                    let stops = day_stops_on(day, &sched).unwrap();
+                    assert_eq!(stops.polar, None);
+                    for ts in [stops.dawn, stops.sunrise, stops.sunset, stops.night] {
+                        assert_eq!(local_date(ts).unwrap(), day, "{zone} {day}");
+                    }
+                    assert!(stops.dawn < stops.sunrise && stops.sunrise < stops.sunset);
+                    let sunrise = format_hhmm(stops.sunrise);
+                    assert!(
+                        ("04:00".."09:00").contains(&sunrise.as_str()),
+                        "{zone} {day} {sunrise}"
+                    );
+                    day = day.succ_opt().unwrap();
+                }
+            });
+        }
+    }
+
+    const EASINGS: [Easing; 5] = [
+        Easing::Linear,
+        Easing::Smoothstep,
+        Easing::Cosine,
+        Easing::Exponential,
+        Easing::Mired,
+    ];
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * a.abs().max(1.0)
+    }
+
+    #[test]
+    fn easings_start_and_end_at_the_keyframes() {
+        for easing in EASINGS {
+            assert!(close(easing.shape(0.0), 0.0), "{easing:?}");
+            assert!(close(easing.shape(1.0), 1.0), "{easing:?}");
+            for (a, b) in [(4000, 6500), (6500, 2700)] {
+                assert!(close(easing.kelvin(0.0, a, b), a as f64), "{easing:?}");
+                assert!(close(easing.kelvin(1.0, a, b), b as f64), "{easing:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn smoothstep_and_cosine_are_symmetric() {
+        for easing in [Easing::Smoothstep, Easing::Cosine] {
+            assert!(close(easing.shape(0.5), 0.5), "{easing:?}");
+            for i in 0..=20 {
+                let t = i as f64 / 20.0;
+                let mirrored = 1.0 - easing.shape(1.0 - t);
+                assert!(close(easing.shape(t), mirrored), "{easing:?} at {t}");
+            }
+        }
+    }
+
+    #[test]
+    fn mired_is_linear_in_mireds() {
+        let (a, b) = (6500, 2700);
+        let mired = |t| 1e6 / Easing::Mired.kelvin(t, a, b);
+        let step = mired(0.1) - mired(0.0);
+        for i in 1..=10 {
+            let t = i as f64 / 10.0;
+            let previous = (i - 1) as f64 / 10.0;
+            assert!(close(mired(t) - mired(previous), step), "at {t}");
+        }
+    }
+
+    #[test]
+    fn exponential_keeps_a_constant_ratio() {
+        let (a, b) = (2700, 6500);
+        let kelvin = |t| Easing::Exponential.kelvin(t, a, b);
+        let ratio = kelvin(0.1) / kelvin(0.0);
+        for i in 1..=10 {
+            let t = i as f64 / 10.0;
+            let previous = (i - 1) as f64 / 10.0;
+            assert!(close(kelvin(t) / kelvin(previous), ratio), "at {t}");
+        }
+    }
+
+    #[test]
+    fn rules_apply_per_day_across_midnight() {
+        let settings = Settings::parse(
+            &[],
+            r#"
+            [[rule]]
+            name = "holidays"
+            dates = ["2026-12-24..2026-12-25"]
+            keyframe = [{ at = "08:00", temp = 5000 }, { at = "20:00", temp = 3000 }]
+
+            [[rule]]
+            name = "fridays"
+            days = ["fri"]
+            keyframe = [{ at = "12:00", temp = 2000 }]
+            "#,
+        )
+        .unwrap();
+        let rule = |day| settings.schedule.rule_on(day).and_then(|r| r.name.clone());
+        assert_eq!(rule(date(2026, 12, 23)), None);
+        assert_eq!(rule(date(2026, 12, 24)).as_deref(), Some("holidays"));
+        // A Friday, but the first matching rule wins
+        assert_eq!(rule(date(2026, 12, 25)).as_deref(), Some("holidays"));
+        assert_eq!(rule(date(2026, 12, 26)), None);
+        assert_eq!(rule(date(2027, 1, 1)).as_deref(), Some("fridays"));
+
+        // Fixed times: dawn 05:30, sunrise 06:00, sunset 18:00, dusk 18:30
+        let sched = SunSchedule::new(&settings, None);
+        in_zone("Europe/Ljubljana", || {
+            let temp = |day, hh, mm| {
+                let now = at(day, hh, mm);
+                let stops = compute_day_stops(now, &sched).unwrap();
+                evaluate(now as f64, stops, &sched, 4000, 6500)
+                    .unwrap()
+                    .temp
+            };
+            assert_eq!(temp(date(2026, 12, 23), 12, 0), 6500);
+            assert_eq!(temp(date(2026, 12, 23), 18, 30), 4000);
+            // From the default dusk to the rule's first keyframe, 13.5h later
+            assert_eq!(temp(date(2026, 12, 23), 23, 59), 4406);
+            assert_eq!(temp(date(2026, 12, 24), 0, 0), 4407);
+            assert_eq!(temp(date(2026, 12, 24), 8, 0), 5000);
+            assert_eq!(temp(date(2026, 12, 24), 14, 0), 4000);
+            // Across the midnight between two rule days, 12h apart
+            assert_eq!(temp(date(2026, 12, 25), 0, 0), 3667);
+            assert_eq!(temp(date(2026, 12, 25), 20, 0), 3000);
+            // From the rule's last keyframe to the default dawn, 9.5h later
+            assert_eq!(temp(date(2026, 12, 26), 0, 0), 3421);
+            assert_eq!(temp(date(2026, 12, 26), 5, 30), 4000);
+            assert_eq!(temp(date(2026, 12, 26), 12, 0), 6500);
+        });
+    }
+
+    #[test]
+    fn elevation_curve_reports_real_sun_times() {
+        let time = settings(&[]);
+        let elevation = settings(&["--curve", "elevation"]);
+        let ljubljana = Some((46.05, 14.51));
+        in_zone("Europe/Ljubljana", || {
+            let day = date(2026, 10, 16);
+            let plain = day_stops_on(day, &SunSchedule::new(&time, ljubljana)).unwrap();
+            let curve = day_stops_on(day, &SunSchedule::new(&elevation, ljubljana)).unwrap();
+            assert_eq!(plain.sun_times, Some((plain.sunrise, plain.sunset)));
+            assert_eq!(curve.sun_times, plain.sun_times);
+            // The curve's stops are the day elevation crossings instead
+            assert!(curve.sunrise > plain.sunrise && curve.sunset < plain.sunset);
+            assert_eq!(format_hhmm(plain.sunrise), "07:19");
+            assert_eq!(format_hhmm(plain.sunset), "18:15");
+        });
+    }
+
+    #[test]
+    fn polar_day_policy_holds_day_across_consecutive_days() {
+        in_zone("Europe/Oslo", || {
+            let settings = settings(&["--polar", "day"]);
+            let sched = SunSchedule::new(&settings, Some((69.65, 18.96)));
+            for day in [date(2026, 6, 21), date(2026, 6, 22)] {
+                for (hh, mm) in [(0, 0), (0, 1), (1, 0), (12, 0), (23, 0), (23, 59)] {
+                    let now = at(day, hh, mm);
+                    let stops = compute_day_stops(now, &sched).unwrap();
+                    assert_eq!(stops.polar, Some(Polar::Day));
+                    let target = evaluate(now as f64, stops, &sched, 4000, 6500).unwrap();
+                    assert_eq!(target.temp, 6500, "{day} {hh:02}:{mm:02}");
+                    assert_eq!(target.phase, DayPhase::Day, "{day} {hh:02}:{mm:02}");
+                }
+            }
+        });
     }
 }
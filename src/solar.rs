@@ -0,0 +1,96 @@
+/// Elevation of the sun's centre at sunrise and sunset, allowing for
+/// refraction and the sun's radius.
+pub const HORIZON: f64 = -0.833;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polar {
+    /// The sun stays above the horizon all day (midnight sun)
+    Day,
+    /// The sun stays below the horizon all day
+    Night,
+}
+
+impl Polar {
+    /// Name used in status reports.
+    pub fn name(self) -> &'static str {
+        match self {
+            Polar::Day => "polar_day",
+            Polar::Night => "polar_night",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SunPosition {
+    /// Degrees above the horizon
+    pub elevation: f64,
+    /// Degrees from solar noon, negative in the morning
+    pub hour_angle: f64,
+    /// Degrees north of the celestial equator
+    pub declination: f64,
+}
+
+/// Sun position at `ts` from the NOAA solar calculator equations, accurate
+/// to well under a degree.
+pub fn position(ts: i64, lat: f64, lon: f64) -> SunPosition {
+    let jd = ts as f64 / 86400.0 + 2440587.5;
+    let t = (jd - 2451545.0) / 36525.0;
+
+    let mean_longitude = (280.46646 + t * (36000.76983 + t * 0.0003032)).rem_euclid(360.0);
+    let mean_anomaly = (357.52911 + t * (35999.05029 - 0.0001537 * t)).to_radians();
+    let eccentricity = 0.016708634 - t * (0.000042037 + 0.0000001267 * t);
+    let center = mean_anomaly.sin() * (1.914602 - t * (0.004817 + 0.000014 * t))
+        + (2.0 * mean_anomaly).sin() * (0.019993 - 0.000101 * t)
+        + (3.0 * mean_anomaly).sin() * 0.000289;
+    let omega = (125.04 - 1934.136 * t).to_radians();
+    let apparent_longitude =
+        (mean_longitude + center - 0.00569 - 0.00478 * omega.sin()).to_radians();
+    let obliquity = (23.0
+        + (26.0 + (21.448 - t * (46.815 + t * (0.00059 - t * 0.001813))) / 60.0) / 60.0
+        + 0.00256 * omega.cos())
+    .to_radians();
+    let declination = (obliquity.sin() * apparent_longitude.sin()).asin();
+
+    // Equation of time, in minutes
+    let y = (obliquity / 2.0).tan().powi(2);
+    let l0 = mean_longitude.to_radians();
+    let equation_of_time = 4.0
+        * (y * (2.0 * l0).sin() - 2.0 * eccentricity * mean_anomaly.sin()
+            + 4.0 * eccentricity * y * mean_anomaly.sin() * (2.0 * l0).cos()
+            - 0.5 * y * y * (4.0 * l0).sin()
+            - 1.25 * eccentricity * eccentricity * (2.0 * mean_anomaly).sin())
+        .to_degrees();
+
+    let utc_minutes = ts.rem_euclid(86400) as f64 / 60.0;
+    let solar_minutes = (utc_minutes + equation_of_time + 4.0 * lon).rem_euclid(1440.0);
+    let hour_angle = solar_minutes / 4.0 - 180.0;
+
+    let lat = lat.to_radians();
+    let cos_zenith = lat.sin() * declination.sin()
+        + lat.cos() * declination.cos() * hour_angle.to_radians().cos();
+    SunPosition {
+        elevation: 90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees(),
+        hour_angle,
+        declination: declination.to_degrees(),
+    }
+}
+
+/// Lowest and highest elevation the sun reaches on the day around `ts`.
+pub fn elevation_range(ts: i64, lat: f64, lon: f64) -> (f64, f64) {
+    let declination = position(ts, lat, lon).declination;
+    let lowest = (lat + declination).abs() - 90.0;
+    let highest = 90.0 - (lat - declination).abs();
+    (lowest, highest)
+}
+
+/// Whether the sun neither rises nor sets on the day around `ts`.
+pub fn polar_state(ts: i64, lat: f64, lon: f64) -> Option<Polar> {
+    let (lowest, highest) = elevation_range(ts, lat, lon);
+    if lowest > HORIZON {
+        Some(Polar::Day)
+    } else if highest < HORIZON {
+        Some(Polar::Night)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,72 @@
+use std::env;
+use std::fs;
+
+/// IANA zone name and representative coordinates, one zone per line.
+const ZONES: &str = include_str!("../data/zones.tab");
+
+/// Name of the configured IANA timezone: `$TZ`, then the `/etc/localtime`
+/// symlink, then `/etc/timezone`.
+pub fn local_zone() -> Option<String> {
+    if let Ok(tz) = env::var("TZ") {
+        let tz = tz.trim_start_matches(':');
+        // `TZ` may also be a path into the zoneinfo tree
+        let name = tz.rsplit_once("zoneinfo/").map_or(tz, |(_, name)| name);
+        if !name.is_empty() {
+            return Some(name.to_string());
+        }
+    }
+    if let Ok(target) = fs::read_link("/etc/localtime")
+        && let Some((_, name)) = target.to_string_lossy().rsplit_once("zoneinfo/")
+    {
+        return Some(name.to_string());
+    }
+    fs::read_to_string("/etc/timezone")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+/// Representative `(lat, lon)` of a zone, usually its principal city.
+/// `None` for zones without a location such as `UTC` or `Etc/GMT+2`.
+pub fn zone_coordinates(zone: &str) -> Option<(f64, f64)> {
+    ZONES
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once('\t'))
+        .find(|(name, _)| *name == zone)
+        .and_then(|(_, coords)| parse_iso6709(coords))
+}
+
+/// Estimates the location from the configured timezone.
+pub fn estimate() -> Option<(String, (f64, f64))> {
+    let zone = local_zone()?;
+    let location = zone_coordinates(&zone)?;
+    Some((zone, location))
+}
+
+/// Parses tzdb coordinates, `±DDMM±DDDMM` or `±DDMMSS±DDDMMSS`.
+fn parse_iso6709(s: &str) -> Option<(f64, f64)> {
+    let split = s[1..].find(['+', '-'])? + 1;
+    let (lat, lon) = s.split_at(split);
+    Some((parse_angle(lat, 2)?, parse_angle(lon, 3)?))
+}
+
+fn parse_angle(s: &str, degree_digits: usize) -> Option<f64> {
+    let (sign, digits) = match s.split_at_checked(1)? {
+        ("+", digits) => (1.0, digits),
+        ("-", digits) => (-1.0, digits),
+        _ => return None,
+    };
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| -> Option<f64> { digits.get(range)?.parse().ok() };
+    let degrees = field(0..degree_digits)?;
+    let minutes = field(degree_digits..degree_digits + 2)?;
+    let seconds = match digits.len() - degree_digits {
+        2 => 0.0,
+        4 => field(degree_digits + 2..degree_digits + 4)?,
+        _ => return None,
+    };
+    Some(sign * (degrees + minutes / 60.0 + seconds / 3600.0))
+}
@@ -1,5 +1,4 @@
 use anyhow::Result;
-use bytemuck;
 use memmap2::MmapMut;
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
@@ -15,7 +14,9 @@ use wayland_protocols_wlr::gamma_control::v1::client::{
     zwlr_gamma_control_manager_v1, zwlr_gamma_control_v1,
 };
 
-use crate::color::{blackbody_whitepoint_kelvin, fill_gamma_table};
+use crate::color::{Gamma, blackbody_whitepoint_kelvin, fill_gamma_table, floored_brightness};
+use crate::ipc::OutputStatus;
+use crate::outputs::{OutputFilter, OutputProfile, OutputProfiles};
 
 #[derive(Clone, Copy)]
 pub struct OutputData {
@@ -29,27 +30,59 @@ pub struct GammaData {
 
 pub struct OutputState {
     pub name: Option<String>,
+    pub description: Option<String>,
+    /// Set once the initial burst of output events (name, description) has
+    /// been received, so the filter can be evaluated.
+    pub ready: bool,
     pub wl_output: wl_output::WlOutput,
     pub gamma: Option<zwlr_gamma_control_v1::ZwlrGammaControlV1>,
     pub ramp_size: u32,
     pub table: Option<(File, MmapMut)>,
+    pub profile: OutputProfile,
+    pub applied: Option<AppliedGamma>,
+    /// Gamma tables sent to this output so far
+    pub uploads: u64,
+}
+
+/// Parameters of the gamma table last sent to an output. A table with the
+/// same parameters is neither regenerated nor sent again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AppliedGamma {
+    pub kelvin: i32,
+    pub gamma: Gamma,
+    pub brightness: f64,
+    pub ramp_size: u32,
 }
 
 pub struct AppState {
     pub outputs: HashMap<u32, OutputState>,
     pub gamma_mgr: Option<zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1>,
     pub gamma_mgr_name: Option<u32>,
+    pub filter: OutputFilter,
+    pub profiles: OutputProfiles,
 }
 
 impl AppState {
-    pub fn new() -> Self {
+    pub fn new(filter: OutputFilter, profiles: OutputProfiles) -> Self {
         Self {
             outputs: HashMap::new(),
             gamma_mgr: None,
             gamma_mgr_name: None,
+            filter,
+            profiles,
         }
     }
 
+    fn is_selected(&self, id: u32) -> bool {
+        self.outputs.get(&id).is_some_and(|o| {
+            o.ready
+                && o.profile.enabled
+                && self
+                    .filter
+                    .matches(o.name.as_deref(), o.description.as_deref())
+        })
+    }
+
     pub fn ensure_gamma_for(&mut self, qh: &QueueHandle<Self>, id: u32) {
         let Some(mgr) = self.gamma_mgr.clone() else {
             return;
@@ -62,6 +95,9 @@ impl AppState {
         {
             return;
         }
+        if !self.is_selected(id) {
+            return;
+        }
         let Some(wl_output) = self.outputs.get(&id).map(|o| o.wl_output.clone()) else {
             return;
         };
@@ -81,6 +117,66 @@ impl AppState {
     pub fn remove_output(&mut self, id: u32) {
         self.outputs.remove(&id);
     }
+
+    /// Swaps in a new filter and profile set, releasing gamma controls of
+    /// outputs that are no longer selected and creating missing ones.
+    pub fn reconfigure(
+        &mut self,
+        qh: &QueueHandle<Self>,
+        filter: OutputFilter,
+        profiles: OutputProfiles,
+    ) {
+        self.filter = filter;
+        self.profiles = profiles;
+        let ids: Vec<u32> = self.outputs.keys().copied().collect();
+        for id in ids {
+            self.resolve_profile(id);
+            if !self.is_selected(id) {
+                self.release_gamma(id);
+            }
+        }
+        self.ensure_gamma_all(qh);
+    }
+
+    /// Destroying the gamma control makes the compositor restore the
+    /// output's original ramp.
+    fn release_gamma(&mut self, id: u32) {
+        let Some(output) = self.outputs.get_mut(&id) else {
+            return;
+        };
+        if let Some(gamma) = output.gamma.take() {
+            gamma.destroy();
+        }
+        output.table = None;
+        output.ramp_size = 0;
+        output.applied = None;
+    }
+
+    fn resolve_profile(&mut self, id: u32) {
+        if let Some(output) = self.outputs.get_mut(&id) {
+            output.profile = self
+                .profiles
+                .resolve(output.name.as_deref(), output.description.as_deref());
+        }
+    }
+
+    pub fn output_statuses(&self) -> Vec<OutputStatus> {
+        let mut statuses: Vec<OutputStatus> = self
+            .outputs
+            .values()
+            .map(|o| OutputStatus {
+                name: o.name.clone(),
+                description: o.description.clone(),
+                enabled: o.gamma.is_some(),
+                temperature: o.applied.map(|a| a.kelvin),
+                gamma: o.applied.map(|a| a.gamma),
+                brightness: o.applied.map(|a| a.brightness),
+                uploads: o.uploads,
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        statuses
+    }
 }
 
 impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
@@ -109,10 +205,16 @@ impl Dispatch<wl_registry::WlRegistry, ()> for AppState {
                         name,
                         OutputState {
                             name: None,
+                            description: None,
+                            // wl_output v1 has no done event to wait for
+                            ready: version < 2,
                             wl_output,
                             gamma: None,
                             ramp_size: 0,
                             table: None,
+                            profile: OutputProfile::default(),
+                            applied: None,
+                            uploads: 0,
                         },
                     );
                     state.ensure_gamma_for(qh, name);
@@ -150,17 +252,36 @@ impl Dispatch<wl_output::WlOutput, OutputData> for AppState {
         event: wl_output::Event,
         data: &OutputData,
         _: &Connection,
-        _: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
     ) {
         match event {
             wl_output::Event::Name { name } => {
                 if let Some(output) = state.outputs.get_mut(&data.id) {
                     output.name = Some(name);
                 }
+                state.resolve_profile(data.id);
             }
             wl_output::Event::Description { description } => {
                 if let Some(output) = state.outputs.get_mut(&data.id) {
-                    output.name = Some(description);
+                    output.description = Some(description);
+                }
+                state.resolve_profile(data.id);
+            }
+            wl_output::Event::Done => {
+                let Some(output) = state.outputs.get_mut(&data.id) else {
+                    return;
+                };
+                if output.ready {
+                    return;
+                }
+                output.ready = true;
+                if state.is_selected(data.id) {
+                    state.ensure_gamma_for(qh, data.id);
+                } else if let Some(output) = state.outputs.get(&data.id) {
+                    eprintln!(
+                        "Skipping output {:?} ({:?}): not selected",
+                        output.name, output.description
+                    );
                 }
             }
             _ => {}
@@ -181,6 +302,8 @@ impl Dispatch<zwlr_gamma_control_v1::ZwlrGammaControlV1, GammaData> for AppState
             zwlr_gamma_control_v1::Event::GammaSize { size } => {
                 if let Some(output) = state.outputs.get_mut(&data.id) {
                     output.ramp_size = size;
+                    // A fresh table has to be sent even if the values match
+                    output.applied = None;
                     let table_bytes = size as usize * 3 * std::mem::size_of::<u16>();
                     match create_anonymous_file(table_bytes) {
                         Ok(file) => match unsafe { MmapMut::map_mut(&file) } {
@@ -205,6 +328,7 @@ impl Dispatch<zwlr_gamma_control_v1::ZwlrGammaControlV1, GammaData> for AppState
                     output.gamma = None;
                     output.table = None;
                     output.ramp_size = 0;
+                    output.applied = None;
                 }
             }
             _ => {}
@@ -222,6 +346,7 @@ pub fn create_anonymous_file(size: usize) -> Result<File> {
         .read(true)
         .write(true)
         .create(true)
+        .truncate(true)
         .mode(0o600)
         .open(&path)?;
     f.set_len(size as u64)?;
@@ -229,8 +354,18 @@ pub fn create_anonymous_file(size: usize) -> Result<File> {
     Ok(f)
 }
 
-pub fn set_temperature_all(outputs: &mut HashMap<u32, OutputState>, kelvin: i32, gamma: f64) {
-    let wp = blackbody_whitepoint_kelvin(kelvin);
+/// Applies `kelvin` to every output with a gamma control. `low`/`high` is the
+/// global temperature range, used to remap onto per-output profile ranges;
+/// brightness is never taken below `min_brightness`.
+pub fn set_temperature_all(
+    outputs: &mut HashMap<u32, OutputState>,
+    kelvin: i32,
+    low: i32,
+    high: i32,
+    gamma: Gamma,
+    brightness: f64,
+    min_brightness: f64,
+) {
     for output in outputs.values_mut() {
         let Some(ref gamma_obj) = output.gamma else {
             continue;
@@ -241,14 +376,28 @@ pub fn set_temperature_all(outputs: &mut HashMap<u32, OutputState>, kelvin: i32,
         let Some((file, mmap)) = output.table.as_mut() else {
             continue;
         };
+        let applied = AppliedGamma {
+            kelvin: output.profile.kelvin_for(kelvin, low, high),
+            gamma: output.profile.gamma.unwrap_or(gamma),
+            brightness: floored_brightness(output.profile.brightness * brightness, min_brightness),
+            ramp_size: output.ramp_size,
+        };
+        if output.applied == Some(applied) {
+            continue;
+        }
         let ramp = output.ramp_size as usize;
         let u16_slice = bytemuck::cast_slice_mut::<u8, u16>(mmap);
-        fill_gamma_table(u16_slice, ramp, wp, gamma);
+        let wp = blackbody_whitepoint_kelvin(applied.kelvin);
+        fill_gamma_table(u16_slice, ramp, wp, applied.gamma, applied.brightness);
         let _ = file.seek(SeekFrom::Start(0));
-        eprintln!(
-            "Applying gamma to output {:?} (ramp_size: {})",
-            output.name, ramp
-        );
+        if output.applied.is_none() {
+            eprintln!(
+                "Applying gamma to output {:?} (ramp_size: {}, {}K)",
+                output.name, ramp, applied.kelvin
+            );
+        }
         gamma_obj.set_gamma(file.as_fd());
+        output.applied = Some(applied);
+        output.uploads += 1;
     }
 }